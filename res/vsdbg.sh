@@ -13,10 +13,12 @@ if [ "$1" == "" ]; then
 	echo "please specify container id"
 	exit
 fi
+port=${2:-4444}
 echo $1
 docker cp vsdbg.sh $1:/_vsdbg.sh
 docker exec $1 bash /_vsdbg.sh
 containerip=$(docker inspect -f '{{range $key, $value := .NetworkSettings.Networks}}{{if ne $key "ingress"}}{{$value.IPAddress}}{{end}}{{end}}' $1)
 networkid=$(docker inspect -f '{{range $key, $value := .NetworkSettings.Networks}}{{if ne $key "ingress"}}{{$value.NetworkID}}{{end}}{{end}}' $1)
-echo "starting tunnel to $containerip on network $networkid on port $2"
-docker run --rm --network $networkid -p $2:22  alpine/socat tcp-listen:22,fork,reuseaddr tcp-connect:$containerip:22
+echo "starting tunnel to $containerip on network $networkid on port $port"
+docker rm -f tt-vsdbg-$port >/dev/null 2>&1
+docker run --rm --name tt-vsdbg-$port --network $networkid -p 127.0.0.1:$port:22 alpine/socat tcp-listen:22,fork,reuseaddr tcp-connect:$containerip:22
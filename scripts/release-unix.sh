@@ -0,0 +1,30 @@
+#!/bin/sh
+# Adds this machine's build, e.g. linux-x64 or macos-arm64, to the release release-win.ps1 created,
+# one machine at a time since each rewrites tt-manifest.json
+set -e
+cd "$(dirname "$0")/.."
+version=$(sed -n 's/^version = "\([0-9]*\.[0-9]*\.[0-9]*\)"$/\1/p' Cargo.toml)
+case "$(uname -s)" in
+    Linux) os=linux ;;
+    Darwin) os=macos ;;
+    *) echo "unsupported OS $(uname -s)" >&2; exit 1 ;;
+esac
+case "$(uname -m)" in
+    x86_64) arch=x64 ;;
+    arm64 | aarch64) arch=arm64 ;;
+    *) echo "unsupported architecture $(uname -m)" >&2; exit 1 ;;
+esac
+asset="tt-$version-$os-$arch"
+url="https://github.com/paolodellepiane/ash/releases/download/v$version/$asset"
+rm -rf release
+mkdir release
+cargo build --release
+cp target/release/tt "release/$asset"
+cd release
+# 'tt --check-update' reads the first word, sha256sum and shasum print the same
+(sha256sum "$asset" 2>/dev/null || shasum -a 256 "$asset") > "$asset.sha256"
+gh release download "v$version" -p tt-manifest.json
+jq --arg platform "$os-$arch" --arg url "$url" '.artifacts[$platform] = { url: $url, sha256: "\($url).sha256" }' \
+    tt-manifest.json > tt-manifest.json.new
+mv tt-manifest.json.new tt-manifest.json
+gh release upload "v$version" --clobber "$asset" "$asset.sha256" tt-manifest.json
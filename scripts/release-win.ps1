@@ -1,17 +1,25 @@
 $initialdir = pwd
 cd $PSScriptRoot\..
 $version = (cat ./Cargo.toml|Select-String -Pattern  '^version = \"(\d+\.\d+\.\d+)\"$').Matches.Groups[1].Value
+$zip = "tt-$version-win-x64.zip"
 if (Test-Path release) {
     rm -Recurse -Force release
 }
 mkdir release
 cargo build --release
-cp target\release\ash.exe release\
+cp target\release\tt.exe release\
 cp scripts\ash.json release\ash.json
 cd release
-Compress-Archive ash.exe ash-$version-win-x64.zip
-(Get-FileHash .\ash-$version-win-x64.zip).Hash > .\ash-$version-win-x64.zip.sha256
-gh release create v$version --generate-notes .\ash-$version-win-x64.zip .\ash-$version-win-x64.zip.sha256
+Compress-Archive tt.exe $zip
+(Get-FileHash .\$zip).Hash > .\$zip.sha256
+$url = "https://github.com/paolodellepiane/ash/releases/download/v$version/$zip"
+# read by 'tt --check-update' through release_manifest, e.g. https://github.com/paolodellepiane/ash/releases/latest/download/tt-manifest.json
+@{
+    version = $version
+    artifacts = @{ "win-x64" = @{ url = "$url"; sha256 = "$url.sha256" } }
+} | ConvertTo-Json -Depth 3 | Out-File -Encoding ascii tt-manifest.json
+# linux and macOS builds are added to this release by release-unix.sh
+gh release create v$version --generate-notes .\$zip .\$zip.sha256 .\tt-manifest.json
 & $env:UserProfile\scoop\apps\scoop\current\bin\checkver.ps1 ash . -Update
 cat ash.json | gh gist edit 448ec9b86bcdf97faa1d7a3cd9d03d73 -f ash.json -
 cd $initialdir
\ No newline at end of file
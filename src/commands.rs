@@ -1,26 +1,46 @@
+use crate::config::Config;
+use crate::exit;
+use crate::history::FolderHistory;
 use crate::history::History;
+use crate::info::{self, NodeInfo};
+use crate::launch;
+use crate::login;
+use crate::ping::{self, StatusCache};
+use crate::platform::{self, Platform};
 use crate::prelude::*;
+use crate::process;
 use crate::select;
 use crate::select::select_teleport_host;
-use crate::select::SelectArgs;
-use crate::settings::Settings;
+use crate::select::{HostUsage, SelectArgs};
 use crate::settings::COMMON_TSH_ARGS;
+use crate::settings::VSDBGSH_FILE_NAME;
+use crate::settings::{AshArgs, Settings, CONFIG_FILE_NAME};
 use crate::ssh::Ssh;
+use crate::ssh_config;
+use crate::sync;
+use crate::tail::{self, TailKind};
 use crate::teleport::Host;
 use crate::teleport::Hosts;
+use crate::transcript::Transcript;
+use crate::tunnels::{parse_sshd_options, Forward, Tunnel, Tunnels};
 use clap::arg;
 use clap::command;
 use clap::Args;
+use clap::CommandFactory;
 use clap::Subcommand;
+use clap::ValueEnum;
 use itertools::Itertools;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::read;
-use std::fs::read_to_string;
 use std::fs::DirEntry;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Args, Clone, Copy)]
 pub struct TunnelArgs {
@@ -28,6 +48,175 @@ pub struct TunnelArgs {
     local: u16,
     /// Remote port
     remote: u16,
+    /// Keep the tunnel running in background, restarting it when it drops (see 'tt tunnels')
+    #[arg(short, long, default_value_t = false)]
+    detach: bool,
+}
+
+#[derive(Args, Clone, Copy)]
+pub struct ExposeArgs {
+    /// Local port
+    local: u16,
+    /// Port to open on the host, same as local if omitted
+    remote: Option<u16>,
+    /// Keep the tunnel running in background, restarting it when it drops (see 'tt tunnels')
+    #[arg(short, long, default_value_t = false)]
+    detach: bool,
+}
+
+#[derive(Args)]
+pub struct SocksArgs {
+    /// Local proxy port
+    #[arg(default_value_t = 1080)]
+    port: u16,
+    /// Write a PAC file routing only the configured socks.domains through the proxy
+    #[arg(long)]
+    pac: Option<PathBuf>,
+    /// Keep the proxy running in background, restarting it when it drops (see 'tt tunnels')
+    #[arg(short, long, default_value_t = false)]
+    detach: bool,
+}
+
+#[derive(Args)]
+pub struct HostFilter {
+    /// Use every host with this label instead of picking one, e.g. '-l env=prod' ('*' matches any value)
+    #[arg(short, long = "label", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+}
+
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    let (k, v) = s.split_once('=').ok_or_else(|| f!("expected key=value, got '{s}'"))?;
+    Ok((k.to_owned(), v.to_owned()))
+}
+
+#[derive(Args)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    filter: HostFilter,
+    /// Print JSON instead of a table
+    #[arg(long, default_value_t = false)]
+    json: bool,
+}
+
+#[derive(Args)]
+pub struct PingArgs {
+    #[command(flatten)]
+    filter: HostFilter,
+    /// Seconds to wait for each host
+    #[arg(short, long, default_value_t = 10)]
+    timeout: u64,
+}
+
+#[derive(Args)]
+pub struct PickArgs {
+    /// Host field to print
+    #[arg(short, long, value_enum, default_value_t = PickField::Hostname)]
+    field: PickField,
+    /// Print this instead of a field, e.g. '{ssh_name}:{label:env}', failing on a host without the label
+    #[arg(short, long, conflicts_with = "field")]
+    template: Option<String>,
+    /// Mark several hosts with Tab
+    #[arg(short, long, default_value_t = false)]
+    multi: bool,
+    /// End each host with NUL instead of a newline, for 'xargs -0'
+    #[arg(short = '0', long, default_value_t = false)]
+    null: bool,
+    #[command(flatten)]
+    filter: HostFilter,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PickField {
+    Hostname,
+    SshName,
+    Addr,
+    Key,
+}
+
+impl PickField {
+    /// `Host::render` template printing the field
+    fn template(&self) -> &'static str {
+        match self {
+            PickField::Hostname => "{hostname}",
+            PickField::SshName => "{ssh_name}",
+            PickField::Addr => "{addr}",
+            PickField::Key => "{key}",
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct TailArgs {
+    /// File path, journald unit or docker container to follow
+    target: String,
+    /// What target is, guessed if omitted: paths contain '/' or '\', units end with '.service', else a container
+    #[arg(short, long, value_enum)]
+    kind: Option<TailKind>,
+    /// Only show lines matching this regex
+    #[arg(short, long)]
+    grep: Option<String>,
+    /// Lines of history to show first
+    #[arg(short = 'n', long, default_value_t = 10)]
+    lines: u32,
+    #[command(flatten)]
+    filter: HostFilter,
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    /// Remote file as [host:]path, browse for it if omitted
+    target: Option<String>,
+    /// Read and write the file with sudo
+    #[arg(long, default_value_t = false)]
+    sudo: bool,
+}
+
+#[derive(Args)]
+pub struct SyncArgs {
+    /// Source directory, local or [host]:dir
+    from: String,
+    /// Destination directory, [host]:dir when syncing from local, local otherwise
+    to: String,
+    /// Delete destination files missing from the source
+    #[arg(long, default_value_t = false)]
+    delete: bool,
+    /// Only print what would be copied and deleted ('--dry-run' also prints the transfer commands)
+    #[arg(short = 'n', long, default_value_t = false)]
+    plan: bool,
+    /// Compare sha256 checksums instead of size and modification time
+    #[arg(short, long, default_value_t = false)]
+    checksum: bool,
+    /// Skip paths matching this pattern, e.g. '.git', '*.log' or 'target/**'
+    #[arg(short, long)]
+    exclude: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct VsdbgArgs {
+    /// Container id or name, pick from the running ones if omitted
+    container: Option<String>,
+    /// Port the socat tunnel to the container's sshd listens on, on the host
+    #[arg(short, long, default_value_t = 4444)]
+    port: u16,
+    /// Local port to forward, same as port if omitted
+    #[arg(short, long)]
+    local: Option<u16>,
+}
+
+#[derive(Args)]
+pub struct DebugConfigArgs {
+    /// Attach inside a container rather than on the host, pick from the running ones if no name is given
+    #[arg(short, long, num_args = 0..=1, default_missing_value = "")]
+    container: Option<String>,
+    /// Configuration name, replaced if it already exists
+    #[arg(short, long)]
+    name: Option<String>,
+    /// vsdbg path on the target, where vsdbg.sh installs it if omitted
+    #[arg(long)]
+    debugger: Option<String>,
+    /// launch.json to write
+    #[arg(long, default_value = ".vscode/launch.json")]
+    file: PathBuf,
 }
 
 #[derive(Args)]
@@ -41,29 +230,146 @@ pub struct ScpArgs {
 }
 
 fn get_hosts(s: &Settings) -> Result<Vec<Host>> {
-    let hosts = if s.cache_path.exists() {
-        read(&s.cache_path)?
+    login::ensure_login(&s.login_path)?;
+    let mut hosts: Hosts = if s.cache_path.exists() {
+        serde_json::from_slice(&read(&s.cache_path)?)?
     } else {
-        let hosts = Command::new("tsh").args(COMMON_TSH_ARGS).args(["ls", "-f", "json"]).output()?.stdout;
-        std::fs::write(&s.cache_path, &hosts)?;
+        let out = Command::new("tsh").args(COMMON_TSH_ARGS).args(["ls", "-f", "json"]).query()?;
+        if !out.status.success() {
+            bail!("tsh ls failed: {}", String::from_utf8_lossy(&out.stderr).trim());
+        }
+        let hosts = serde_json::from_slice(&out.stdout).wrap_err("Error deserializing tsh ls output")?;
+        std::fs::write(&s.cache_path, &out.stdout)?;
         hosts
     };
-    let hosts: Hosts = serde_json::from_slice(&hosts)?;
+    let label = s.config.platform_label.as_deref().unwrap_or(platform::DEFAULT_LABEL);
+    hosts.iter_mut().for_each(|x| x.detect_platform(label));
     Ok(hosts)
 }
 
-fn add_recents(mut hosts: Vec<Host>, s: &Settings) -> Vec<Host> {
-    let recents = History::load(&s.history_path).intersect(&hosts).entries;
+fn add_recents(mut hosts: Vec<Host>, s: &Settings) -> Result<Vec<Host>> {
+    // fresh copies, history entries have stale labels and no platform
+    let recents = History::load(&s.history_path)?.intersect(&hosts)?
+                                                 .entries
+                                                 .iter()
+                                                 .filter_map(|x| hosts.iter().find(|h| *h == x).cloned())
+                                                 .collect_vec();
     hosts.retain(|x| !recents.contains(x));
-    [recents, hosts].concat()
+    Ok([recents, hosts].concat())
+}
+
+/// `hosts` with the ones keyed in `favorites` first, in that order
+fn add_favorites(mut hosts: Vec<Host>, favorites: &[String]) -> Vec<Host> {
+    let favorites = favorites.iter().filter_map(|k| hosts.iter().find(|h| h.key() == k).cloned()).collect_vec();
+    hosts.retain(|x| !favorites.contains(x));
+    [favorites, hosts].concat()
+}
+
+fn host_by_key(hosts: Vec<Host>, key: &str) -> Result<Host> {
+    hosts.into_iter().find(|h| h.key() == key).ok_or_else(|| eyre!("No host with key {key}"))
 }
 
 fn select_host(s: &Settings) -> Result<Host> {
-    let hosts = get_hosts(s)?;
-    let hosts = add_recents(hosts, s);
-    let host = select_teleport_host(&SelectArgs { hosts, start_value: s.start_value.clone() })?;
-    History::load(&s.history_path).update(&host);
-    Ok(host)
+    select_host_where(s, |_| true)
+}
+
+fn select_host_where(s: &Settings, filter: impl Fn(&Host) -> bool) -> Result<Host> {
+    select_host_matching(s, &s.start_value, filter)
+}
+
+/// Like `select_host_where`, with `start_value` replacing the host given on the command line
+fn select_host_matching(s: &Settings, start_value: &str, filter: impl Fn(&Host) -> bool) -> Result<Host> {
+    Ok(pick_host(s, start_value, filter, &[])?.0)
+}
+
+/// Host keys, like aliases, resolve directly, so tt can re-run itself on a picked host
+fn pick_host(s: &Settings,
+             start_value: &str,
+             filter: impl Fn(&Host) -> bool,
+             actions: &'static [(char, &'static str)])
+             -> Result<(Host, Option<char>)> {
+    let mut hosts = get_hosts(s)?;
+    hosts.retain(&filter);
+    let (host, action) = match direct_host(&hosts, &s.config.aliases, start_value)? {
+        Some(idx) => (hosts.swap_remove(idx), None),
+        None => select_teleport_host(&select_args(s, hosts, start_value, actions)?)?,
+    };
+    History::load(&s.history_path)?.update(&host)?;
+    Ok((host, action))
+}
+
+/// Like `pick_host`, letting several hosts be marked when `multi`, empty when cancelled
+fn pick_hosts(s: &Settings, start_value: &str, multi: bool) -> Result<Vec<Host>> {
+    let mut hosts = get_hosts(s)?;
+    let picked = match direct_host(&hosts, &s.config.aliases, start_value)? {
+        Some(idx) => vec![hosts.swap_remove(idx)],
+        None => select::select_teleport_hosts(&select_args(s, hosts, start_value, &[])?, multi)?,
+    };
+    for host in &picked {
+        History::load(&s.history_path)?.update(host)?;
+    }
+    Ok(picked)
+}
+
+/// Index of the host `start_value` names as an alias or a host key, `None` when it's a search to start from
+fn direct_host(hosts: &[Host], aliases: &BTreeMap<String, String>, start_value: &str) -> Result<Option<usize>> {
+    let key = aliases.get(start_value).map(String::as_str).unwrap_or(start_value);
+    match hosts.iter().position(|h| h.key() == key) {
+        None if aliases.contains_key(start_value) => bail!("Broken or filtered out alias '{start_value}'"),
+        idx => Ok(idx),
+    }
+}
+
+/// Recents and favorites first, with what the selector shows about them
+fn select_args(s: &Settings,
+               hosts: Vec<Host>,
+               start_value: &str,
+               actions: &'static [(char, &'static str)])
+               -> Result<SelectArgs> {
+    let hosts = add_favorites(add_recents(hosts, s)?, &s.config.favorites);
+    let favorites = s.config.favorites.clone();
+    let statuses = if s.config.picker.reachability { Some(StatusCache::load(&s.status_path)?.entries) } else { None };
+    let usage = host_usage(s, &hosts)?;
+    let start_value = start_value.to_owned();
+    Ok(SelectArgs { hosts, start_value, favorites, statuses, usage, actions })
+}
+
+/// Recents, folders, tunnels and last ping of every host for the selector preview
+fn host_usage(s: &Settings, hosts: &[Host]) -> Result<HashMap<String, HostUsage>> {
+    let mut usage = HashMap::<String, HostUsage>::new();
+    for (i, host) in History::load(&s.history_path)?.entries.iter().enumerate() {
+        usage.entry(host.key().to_owned()).or_default().recent = Some(i);
+    }
+    for (key, folders) in FolderHistory::load(&s.folder_history_path)?.entries {
+        usage.entry(key).or_default().folders = folders;
+    }
+    for (key, status) in StatusCache::load(&s.status_path)?.entries {
+        usage.entry(key).or_default().status = Some(status);
+    }
+    for tunnel in Tunnels::load(&s.tunnels_path)?.entries {
+        if let Some(host) = hosts.iter().find(|h| h.name() == tunnel.host) {
+            usage.entry(host.key().to_owned()).or_default().tunnels.push(f!("#{} {}", tunnel.id, tunnel.describe()));
+        }
+    }
+    Ok(usage)
+}
+
+/// Every host matching the filter labels, or the one picked interactively when there are none
+fn select_hosts(s: &Settings, HostFilter { labels }: &HostFilter) -> Result<Vec<Host>> {
+    if labels.is_empty() {
+        return Ok(vec![select_host(s)?]);
+    }
+    let selector = labels.iter().cloned().collect::<BTreeMap<_, _>>();
+    let hosts = get_hosts(s)?.into_iter().filter(|h| h.matches(&selector)).collect_vec();
+    ensure!(!hosts.is_empty(), "No host matches {selector:?}");
+    Ok(hosts)
+}
+
+fn select_or_find_host(s: &Settings, key: &Option<String>) -> Result<Host> {
+    match key {
+        Some(key) => host_by_key(get_hosts(s)?, key),
+        None => select_host(s),
+    }
 }
 
 #[derive(Subcommand)]
@@ -77,18 +383,63 @@ pub enum Commands {
     //     /// Common Services
     //     service: Service,
     // },
-    // /// Create a tunnel for custom ports
-    // #[command(arg_required_else_help = true)]
-    // Tunnel(TunnelArgs),
+    /// Create a tunnel for custom ports
+    #[command(arg_required_else_help = true)]
+    Tunnel(TunnelArgs),
+    /// Open a SOCKS proxy through the host
+    #[command()]
+    Socks(SocksArgs),
+    /// Expose a local port on the host
+    #[command(arg_required_else_help = true)]
+    Expose(ExposeArgs),
+    /// List or stop background tunnels
+    #[command(arg_required_else_help = true)]
+    Tunnels {
+        #[command(subcommand)]
+        action: TunnelsCommands,
+    },
     /// Execute a command remotely
     #[command(arg_required_else_help = true)]
     Exec {
         /// Command to execute
         command: String,
     },
+    /// Run a saved snippet, pick one from those applying to the host if omitted
+    #[command()]
+    Run {
+        /// Snippet name
+        snippet: Option<String>,
+    },
+    /// Show uptime, load, memory, disk, OS and running containers
+    #[command()]
+    Info(InfoArgs),
+    /// Check hosts are reachable and show the round trip latency
+    #[command()]
+    Ping(PingArgs),
+    /// Print the selected host for scripts, e.g. 'ssh $(tt pick -f ssh-name)', the selector is drawn on stderr
+    #[command()]
+    Pick(PickArgs),
+    /// Follow a file, journald unit or container log on one or more hosts
+    #[command(arg_required_else_help = true)]
+    Tail(TailArgs),
+    /// Edit a remote file in $EDITOR
+    #[command()]
+    Edit(EditArgs),
+    /// Copy only changed files of a directory to or from a host
+    #[command(arg_required_else_help = true)]
+    Sync(SyncArgs),
+    /// Prepare a container for remote .NET debugging and forward its sshd locally until Ctrl-C
+    #[command()]
+    Vsdbg(VsdbgArgs),
+    /// Add a VS Code attach configuration for the host or one of its containers to launch.json
+    #[command()]
+    DebugConfig(DebugConfigArgs),
     /// Connect vscode to remote host
     #[command()]
-    Code,
+    Code {
+        /// Remote folder or .code-workspace file, pick from recents or browse if omitted
+        path: Option<String>,
+    },
     /// Get file
     #[command()]
     Get {
@@ -104,9 +455,34 @@ pub enum Commands {
     /// Get windows event logs
     #[command()]
     EventLog,
-    /// Append teleport config to ssh config
+    /// Manage tt's config and the generated Teleport block in ~/.ssh/config (installs the block by default)
+    #[command()]
+    Config {
+        #[command(subcommand)]
+        action: Option<ConfigCommands>,
+    },
+    /// Show where tt keeps its config, cache and history (see XDG_*_HOME and TT_CONFIG_DIR)
+    #[command()]
+    Paths,
+    /// Open a recorded session transcript
     #[command()]
-    Config,
+    Logs {
+        /// Only print transcript paths, newest first
+        #[arg(short, long, default_value_t = false)]
+        list: bool,
+    },
+    /// Manage host aliases (e.g. 'tt alias add db1 <host-key>', then 'tt db1')
+    #[command(arg_required_else_help = true)]
+    Alias {
+        #[command(subcommand)]
+        action: AliasCommands,
+    },
+    /// Manage favorite hosts, always listed first and marked with ★
+    #[command(arg_required_else_help = true)]
+    Fav {
+        #[command(subcommand)]
+        action: FavCommands,
+    },
     // #[command()]
     // Container {
     //     #[command(subcommand)]
@@ -114,6 +490,90 @@ pub enum Commands {
     // },
 }
 
+#[derive(Subcommand)]
+pub enum TunnelsCommands {
+    /// List background tunnels
+    #[command()]
+    Ls,
+    /// Stop background tunnels
+    #[command(arg_required_else_help = true)]
+    Stop {
+        /// Tunnel id, or 'all'
+        id: String,
+    },
+    /// Keep a tunnel up, spawned by 'tt tunnel --detach'
+    #[command(hide = true)]
+    Supervise { id: u32 },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Add the Teleport block if missing
+    #[command()]
+    Install,
+    /// Regenerate the Teleport block, replacing a stale one
+    #[command()]
+    Update,
+    /// Show what 'update' would change
+    #[command()]
+    Diff,
+    /// Remove the Teleport block
+    #[command()]
+    Remove,
+    /// Print tt's effective config, defaults included
+    #[command()]
+    Show,
+    /// Open tt's config in $EDITOR, saving it only once valid
+    #[command()]
+    Edit,
+    /// Check a config file against the schema, tt's own if omitted
+    #[command()]
+    Validate { file: Option<PathBuf> },
+    /// Print the JSON schema of tt's config
+    #[command()]
+    Schema,
+}
+
+#[derive(Subcommand)]
+pub enum AliasCommands {
+    /// Add or replace an alias
+    #[command(arg_required_else_help = true)]
+    Add {
+        /// Alias name
+        name: String,
+        /// Host key, select it from a list if omitted
+        host: Option<String>,
+    },
+    /// Remove an alias
+    #[command(arg_required_else_help = true)]
+    Rm {
+        /// Alias name
+        name: String,
+    },
+    /// List aliases
+    #[command()]
+    Ls,
+}
+
+#[derive(Subcommand)]
+pub enum FavCommands {
+    /// Pin a host to the top of the list
+    #[command()]
+    Add {
+        /// Host key, select it from a list if omitted
+        host: Option<String>,
+    },
+    /// Unpin a host
+    #[command(arg_required_else_help = true)]
+    Rm {
+        /// Host key
+        host: String,
+    },
+    /// List favorite hosts
+    #[command()]
+    Ls,
+}
+
 #[derive(Subcommand)]
 pub enum Container {
     /// Execute a command in a remote container
@@ -167,6 +627,101 @@ pub enum Container {
 //     Self::tunnel_from_ports(TunnelArgs { local, remote }, hosts)
 // }
 
+pub fn tunnel(s: &Settings, TunnelArgs { local, remote, detach }: &TunnelArgs) -> Result<()> {
+    let host = select_host(s)?;
+    start_tunnel(s,
+                 Tunnel::new(host.name(), host.platform().login(), Forward::Local, *local, *remote),
+                 *detach)
+}
+
+fn start_tunnel(s: &Settings, tunnel: Tunnel, detach: bool) -> Result<()> {
+    if detach {
+        let tunnel = Tunnels::spawn(&s.tunnels_path, tunnel)?;
+        if !process::is_dry_run() {
+            p!("Tunnel {} started in background: {}", tunnel.id, tunnel.describe());
+        }
+        return Ok(());
+    }
+    p!("Tunneling {} ...", tunnel.describe());
+    tunnel.command().run()?;
+    Ok(())
+}
+
+pub fn socks(s: &Settings, SocksArgs { port, pac, detach }: &SocksArgs) -> Result<()> {
+    let domains = &s.config.socks.domains;
+    if pac.is_some() {
+        ensure!(!domains.is_empty(),
+                "No domains to route, add them to socks.domains in {}",
+                s.config_path.display());
+    }
+    let host = select_host(s)?;
+    let tunnel = Tunnel::new(host.name(), host.platform().login(), Forward::Dynamic, *port, 0);
+    if let Some(pac) = pac {
+        std::fs::write(pac, tunnel.pac(domains))?;
+        p!("PAC file written to {}", pac.display());
+    }
+    start_tunnel(s, tunnel, *detach)
+}
+
+pub fn expose(s: &Settings, ExposeArgs { local, remote, detach }: &ExposeArgs) -> Result<()> {
+    let host = select_host(s)?;
+    let remote = remote.unwrap_or(*local);
+    let sshd = match host.platform() {
+        Platform::Lnx => tsh_output(&host, "sudo -n sshd -T 2>/dev/null || cat /etc/ssh/sshd_config"),
+        Platform::Win => tsh_output(&host, "type %ProgramData%\\ssh\\sshd_config"),
+    };
+    let sshd = sshd.map(|x| parse_sshd_options(&x)).unwrap_or_default();
+    if sshd.is_empty() {
+        eprintln!("Warning: can't read sshd settings on {}, assuming defaults",
+                  host.name());
+    }
+    let option = |key: &str| sshd.get(key).map(|x| x.as_str());
+    if matches!(option("allowtcpforwarding"), Some("no" | "local")) || option("disableforwarding") == Some("yes") {
+        eprintln!("Warning: sshd on {} forbids remote forwarding, the tunnel will likely fail",
+                  host.name());
+    }
+    if option("gatewayports") == Some("yes") {
+        // tunnel only nodes have no address
+        let ip = host.spec.addr.rsplit_once(':').map(fst).filter(|x| !x.is_empty()).unwrap_or(&host.spec.hostname);
+        p!("Other services can reach localhost:{local} at {ip}:{remote}");
+    } else {
+        let name = host.name();
+        eprintln!("Warning: GatewayPorts is disabled on {name}, port {remote} is reachable from the node only");
+        p!("Services on {} can reach localhost:{local} at localhost:{remote}",
+           host.name());
+    }
+    start_tunnel(s,
+                 Tunnel::new(host.name(), host.platform().login(), Forward::Remote, *local, remote),
+                 *detach)
+}
+
+pub fn tunnels(s: &Settings, action: &TunnelsCommands) -> Result<()> {
+    match action {
+        TunnelsCommands::Ls => {
+            let tunnels = Tunnels::load(&s.tunnels_path)?.entries;
+            let width = tunnels.iter().map(|x| x.describe().len()).max().unwrap_or_default();
+            for t in tunnels {
+                p!("{:>3}  {:width$}  pid {:<7} since {}  {}",
+                   t.id,
+                   t.describe(),
+                   t.pid,
+                   t.started,
+                   t.status());
+            }
+            Ok(())
+        }
+        TunnelsCommands::Stop { id } => {
+            let id = match id.as_str() {
+                "all" => None,
+                id => Some(id.parse().wrap_err_with(|| f!("Invalid tunnel id '{id}'"))?),
+            };
+            Tunnels::stop(&s.tunnels_path, id)?;
+            Ok(())
+        }
+        TunnelsCommands::Supervise { id } => Tunnels::supervise(&s.tunnels_path, *id),
+    }
+}
+
 pub fn cp(s: &Settings, ScpArgs { from, to }: &ScpArgs) -> Result<()> {
     todo!();
     // fn expand_remote(s: &Settings, start_value: &str, is_from: bool) -> Result<String> {
@@ -201,29 +756,513 @@ pub fn cp(s: &Settings, ScpArgs { from, to }: &ScpArgs) -> Result<()> {
     // Ok(())
 }
 
-pub fn ssh(s: &Settings) -> Result<()> {
-    let host = select_host(s)?;
-    let name = host.name();
-    Command::new("tsh").args(COMMON_TSH_ARGS).args(["ssh", &f!("ubuntu@{name}")]).status()?;
+const LAUNCHER_ACTIONS: &[(char, &str)] = &[('c', "code"),
+                                            ('e', "exec"),
+                                            ('g', "get"),
+                                            ('p', "put"),
+                                            ('t', "tunnel"),
+                                            ('y', "print name"),
+                                            (':', "any command")];
+
+/// `tt` without a subcommand: pick a host, then ssh into it or run the action picked with its key
+pub fn launcher(s: &Settings) -> Result<()> {
+    let (host, action) = pick_host(s, &s.start_value, |_| true, LAUNCHER_ACTIONS)?;
+    let args = match action {
+        None => return ssh_to(s, &host),
+        Some('y') => {
+            p!("{}", host.name());
+            return Ok(());
+        }
+        Some('c') => vec!["code".into()],
+        Some('e') => vec!["exec".into(), prompt("Command")?],
+        Some('g') => vec!["get".into()],
+        Some('p') => vec!["put".into()],
+        Some('t') => vec!["tunnel".into(), prompt("Local port")?, prompt("Remote port")?],
+        Some(_) => {
+            let commands = AshArgs::command().get_subcommands()
+                                             .filter(|x| !x.is_hide_set())
+                                             .map(|x| (x.get_name().to_owned(), x.get_about().map(|x| x.to_string())))
+                                             .collect_vec();
+            let width = commands.iter().map(|x| x.0.len()).max().unwrap_or_default();
+            let options = commands.iter()
+                                  .map(|(name, about)| f!("{name:width$}  {}", about.as_deref().unwrap_or("")))
+                                  .collect_vec();
+            let name = commands[select::select("Command", &options, "")?].0.clone();
+            let args = prompt(&f!("tt {} {name}", host.name()))?;
+            // quoted like in a shell, e.g. tt web exec 'ls -la /tmp'
+            let args = shell_words::split(&args).wrap_err("invalid arguments")?;
+            [vec![name], args].concat()
+        }
+    };
+    // the key makes the new process resolve the same host without asking
+    let mut cmd = Command::new(std::env::current_exe()?);
+    let flags = [(s.args.record, "--record"),
+                 (s.args.verbose, "--verbose"),
+                 (s.args.dry_run, "--dry-run")];
+    cmd.args(flags.iter().filter(|x| x.0).map(|x| x.1)).arg(host.key()).args(args);
+    let status = cmd.run()?;
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
     Ok(())
 }
 
+fn prompt(message: &str) -> Result<String> {
+    Ok(dialoguer::Input::<String>::new().with_prompt(message).allow_empty(true).interact_text()?)
+}
+
+fn ssh_to(s: &Settings, host: &Host) -> Result<()> {
+    let login = host.login();
+    let args = [COMMON_TSH_ARGS, &["ssh", &login]].concat();
+    // the session's output comes through the remote terminal on stdout, stderr only carries tsh's own
+    let (status, last_stderr) = match Transcript::new(s, host)? {
+        Some(t) => {
+            let status = t.record_interactive("tsh", &args)?;
+            eprintln!("Transcript saved to {}", t.path().display());
+            (status, None)
+        }
+        None => exit::status(Command::new("tsh").args(&args))?,
+    };
+    exit::check(status, last_stderr.as_deref())
+}
+
 pub fn exec(s: &Settings, command: &str) -> Result<()> {
     let host = select_host(s)?;
-    let name = host.name();
-    Command::new("tsh").args(COMMON_TSH_ARGS).args(["ssh", &f!("ubuntu@{name}"), command]).status()?;
+    tsh_exec(s, &host, command)
+}
+
+fn tsh_exec(s: &Settings, host: &Host, command: &str) -> Result<()> {
+    let mut cmd = Command::new("tsh");
+    cmd.args(COMMON_TSH_ARGS).args(["ssh", &host.login(), command]);
+    let (status, last_stderr) = match Transcript::new(s, host)? {
+        Some(t) => {
+            let res = t.record(&mut cmd, command)?;
+            eprintln!("Transcript saved to {}", t.path().display());
+            res
+        }
+        None => exit::status(&mut cmd)?,
+    };
+    exit::check(status, last_stderr.as_deref())
+}
+
+/// Simultaneous tsh sessions opened by commands running on many hosts, e.g. `info` and `ping`
+const MAX_SESSIONS: usize = 8;
+
+/// `run` on every host, at most `MAX_SESSIONS` at a time, results in the order of `hosts`
+fn on_hosts<R: Send>(hosts: &[Host], run: impl Fn(&Host) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let mut results = std::thread::scope(|scope| {
+        let worker = || {
+            let mut results = vec![];
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(host) = hosts.get(i) else { return results };
+                results.push((i, run(host)));
+            }
+        };
+        let handles = (0..MAX_SESSIONS.min(hosts.len())).map(|_| scope.spawn(worker)).collect_vec();
+        handles.into_iter().flat_map(|x| x.join().expect("host thread panicked")).collect_vec()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, x)| x).collect()
+}
+
+pub fn info(s: &Settings, InfoArgs { filter, json }: &InfoArgs) -> Result<()> {
+    let hosts = select_hosts(s, filter)?;
+    let collect = |h: &Host| NodeInfo::parse(h.name(), tsh_output(h, info::command(h.platform())));
+    let infos = on_hosts(&hosts, collect);
+    if *json {
+        p!("{}", serde_json::to_string_pretty(&infos)?);
+    } else {
+        p!("{}",
+           table(info::HEADER, &infos.iter().map(NodeInfo::row).collect_vec()));
+    }
+    Ok(())
+}
+
+pub fn ping(s: &Settings, PingArgs { filter, timeout }: &PingArgs) -> Result<()> {
+    let hosts = select_hosts(s, filter)?;
+    let timeout = std::time::Duration::from_secs(*timeout);
+    let statuses = on_hosts(&hosts, |h| ping::probe(h, timeout));
+    if process::is_dry_run() {
+        // the probes didn't run, their made up results would end up in the selector
+        return Ok(());
+    }
+    p!("{}",
+       table(ping::HEADER,
+             &hosts.iter().zip(&statuses).map(|(h, x)| x.row(h.name())).collect_vec()));
+    let down = statuses.iter().filter(|x| x.latency_ms.is_none()).count();
+    StatusCache::load(&s.status_path)?.update(hosts.iter().map(|h| h.key().to_owned()).zip(statuses))?;
+    ensure!(down == 0, "{down} of {} hosts unreachable", hosts.len());
+    Ok(())
+}
+
+pub fn pick(s: &Settings, PickArgs { field, template, multi, null, filter }: &PickArgs) -> Result<()> {
+    let hosts =
+        if filter.labels.is_empty() { pick_hosts(s, &s.start_value, *multi)? } else { select_hosts(s, filter)? };
+    if hosts.is_empty() {
+        // cancelled, fail so 'ssh $(tt pick)' doesn't run without a host
+        std::process::exit(1);
+    }
+    let out = pick_output(&hosts, template.as_deref().unwrap_or(field.template()), *null)?;
+    std::io::stdout().write_all(out.as_bytes())?;
+    Ok(())
+}
+
+/// `template` rendered for every host, each terminated by a newline or a NUL for `xargs -0`
+fn pick_output(hosts: &[Host], template: &str, null: bool) -> Result<String> {
+    let separator = if null { '\0' } else { '\n' };
+    hosts.iter().map(|h| Ok(f!("{}{separator}", h.render(template, str::to_owned)?))).collect()
+}
+
+pub fn tail(s: &Settings, TailArgs { target, kind, grep, lines, filter }: &TailArgs) -> Result<()> {
+    let grep = grep.as_deref().map(regex::Regex::new).transpose()?;
+    let hosts = select_hosts(s, filter)?;
+    let kind = kind.unwrap_or_else(|| TailKind::guess(target));
+    tail::follow(&hosts, kind, target, *lines, grep.as_ref())
+}
+
+fn tsh_output(host: &Host, command: &str) -> Result<String> {
+    Ok(String::from_utf8_lossy(&tsh_output_bytes(host, command)?).into_owned())
+}
+
+/// Stdout of a read only remote command, run even with `--dry-run`
+fn tsh_output_bytes(host: &Host, command: &str) -> Result<Vec<u8>> {
+    let out = Command::new("tsh").args(COMMON_TSH_ARGS).args(["ssh", &host.login(), command]).query()?;
+    if !out.status.success() {
+        bail!("{}", String::from_utf8_lossy(&out.stderr));
+    }
+    Ok(out.stdout)
+}
+
+/// Runs a remote command changing something, only printed with `--dry-run`
+fn tsh_change(host: &Host, command: &str) -> Result<()> {
+    let out = Command::new("tsh").args(COMMON_TSH_ARGS).args(["ssh", &host.login(), command]).run_output()?;
+    if !out.status.success() {
+        bail!("{}", String::from_utf8_lossy(&out.stderr));
+    }
+    Ok(())
+}
+
+pub fn edit(s: &Settings, EditArgs { target, sudo }: &EditArgs) -> Result<()> {
+    let (start_value, path) = match target.as_deref().map(|x| x.split_once(':').unwrap_or(("", x))) {
+        Some((host, path)) if !host.is_empty() => (host, Some(path)),
+        Some((_, path)) => (s.start_value.as_str(), Some(path)),
+        None => (s.start_value.as_str(), None),
+    };
+    ssh_config::ensure_installed(&s.login_path)?;
+    let host = select_host_matching(s, start_value, |_| true)?;
+    ensure!(host.platform() == Platform::Lnx,
+            "tt edit supports Linux hosts only, {} runs Windows",
+            host.name());
+    let path = match path {
+        Some(path) if !path.is_empty() => path.to_owned(),
+        _ => browse_remote(&host, false)?,
+    };
+    let sudo = if *sudo { host.platform().sudo() } else { "" };
+    let quoted = shell_quote(&path);
+    let original = tsh_output_bytes(&host, &f!("{sudo}cat {quoted}"))?;
+    let file_name = Path::new(&path).file_name().context("path has no file name")?;
+    let mut dir = TempDir::new("tt-edit")?;
+    let local = dir.path.join(file_name);
+    std::fs::write(&local, &original)?;
+    let status = run_editor(&local)?;
+    ensure!(status.success(), "editor exited with {status}, {} left untouched", path);
+    let edited = std::fs::read(&local)?;
+    if edited == original {
+        p!("No changes");
+        return Ok(());
+    }
+    // From here on the copy holds the user's edits, kept in the private dir when saving fails
+    dir.keep = true;
+    // by content, a same size edit within the same second would fool size and mtime
+    let sha256 = |bytes: &[u8]| Sha256::digest(bytes).iter().map(|x| f!("{x:02x}")).collect::<String>();
+    let current = tsh_output(&host, &f!("{sudo}sha256sum {quoted}"))?;
+    if current.split_whitespace().next() != Some(&sha256(&original)) {
+        bail!("{path} was modified on {} during the edit, not overwriting it. Your copy is in {}",
+              host.name(),
+              local.display());
+    }
+    let replace = f!("tmp=$(mktemp \"$HOME/.tt-edit.XXXXXX\") || exit 1; \
+                      cat > \"$tmp\" && {sudo}cp \"$tmp\" {quoted}; rc=$?; rm -f \"$tmp\"; exit $rc");
+    let mut child = Command::new("tsh").args(COMMON_TSH_ARGS)
+                                       .args(["ssh", &host.login(), &replace])
+                                       .stdin(Stdio::piped())
+                                       .run_spawn()?;
+    let mut stdin = child.stdin.take().context("can't take tsh stdin")?;
+    let written = if process::is_dry_run() { Ok(()) } else { stdin.write_all(&edited) };
+    drop(stdin);
+    let status = child.wait()?;
+    ensure!(status.success() && written.is_ok(),
+            "can't replace {path}, your copy is in {}",
+            local.display());
+    dir.keep = false;
+    p!("Saved {}:{path}", host.name());
+    Ok(())
+}
+
+/// Scratch directory only the user can read, removed when dropped unless `keep` is set
+struct TempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempDir {
+    fn new(prefix: &str) -> Result<Self> {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.subsec_nanos();
+        let path = std::env::temp_dir().join(f!("{prefix}-{}-{nanos}", std::process::id()));
+        let mut builder = std::fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        // `create` fails on an existing path, never reusing a directory someone else prepared
+        builder.create(&path).wrap_err_with(|| f!("can't create {}", path.display()))?;
+        Ok(Self { path, keep: false })
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// `host:dir` split into its parts, `None` for local paths including Windows drive letters
+fn split_remote(arg: &str) -> Option<(&str, &str)> {
+    arg.split_once(':').filter(|(host, _)| host.len() != 1 || !host.chars().all(|x| x.is_ascii_alphabetic()))
+}
+
+pub fn sync(s: &Settings, SyncArgs { from, to, delete, plan: plan_only, checksum, exclude }: &SyncArgs) -> Result<()> {
+    let (upload, (start_value, remote), local) = match (split_remote(from), split_remote(to)) {
+        (None, Some(remote)) => (true, remote, from),
+        (Some(remote), None) => (false, remote, to),
+        _ => bail!("Exactly one of FROM and TO must be a remote [host]:dir"),
+    };
+    ensure!(!remote.is_empty(), "Remote directory can't be empty");
+    let local = Path::new(local);
+    let excludes = sync::Excludes::new(exclude)?;
+    let host = select_host_matching(s, start_value, |_| true)?;
+    ensure!(host.platform() == Platform::Lnx,
+            "tt sync supports Linux hosts only, {} runs Windows",
+            host.name());
+    let remote_files = sync::list_remote(&host, remote, *checksum, upload)?;
+    let local_files = sync::list_local(local, *checksum, !upload)?;
+    let plan = if upload {
+        sync::plan(&local_files, &remote_files, *delete, &excludes)
+    } else {
+        sync::plan(&remote_files, &local_files, *delete, &excludes)
+    };
+    for file in &plan.copy {
+        p!("copy    {file}");
+    }
+    for file in &plan.delete {
+        p!("delete  {file}");
+    }
+    if *plan_only {
+        p!("Plan only: {} to copy, {} to delete",
+           plan.copy.len(),
+           plan.delete.len());
+        return Ok(());
+    }
+    if upload {
+        sync::upload(&host, local, remote, &plan)?;
+    } else {
+        sync::download(&host, remote, local, &plan)?;
+    }
+    let (copied, deleted) = (plan.copy.len(), plan.delete.len());
+    if process::is_dry_run() {
+        p!("Would sync {copied} files, delete {deleted}");
+    } else {
+        p!("Synced {copied} files, deleted {deleted}");
+    }
+    Ok(())
+}
+
+/// Id of a running container, picked from `docker ps`
+fn select_container(host: &Host, start_value: &str) -> Result<String> {
+    let platform = host.platform();
+    let ps = f!("{}d ps --format '{{{{.ID}}}} {{{{.Names}}}} {{{{.Image}}}}'",
+                platform.docker());
+    let containers = tsh_output(host, &platform.script(&ps))?;
+    let containers = containers.lines().map(|x| x.to_owned()).collect_vec();
+    ensure!(!containers.is_empty(), "No running containers on {}", host.name());
+    let container = select::select_str("Container", &containers, start_value)?;
+    Ok(container.split_whitespace().next().context("empty container line")?.to_owned())
+}
+
+pub fn vsdbg(s: &Settings, VsdbgArgs { container, port, local }: &VsdbgArgs) -> Result<()> {
+    ssh_config::ensure_installed(&s.login_path)?;
+    let host = select_host(s)?;
+    ensure!(host.platform() == Platform::Lnx,
+            "{VSDBGSH_FILE_NAME} needs a Linux host, {} runs Windows",
+            host.name());
+    let container = select_container(&host, container.as_deref().unwrap_or_default())?;
+    let status = scp_execute(&s.vsdbgsh_path.to_string_lossy(),
+                             &f!("{}:{VSDBGSH_FILE_NAME}", host.ssh_login()))?;
+    ensure!(status.success(), "can't upload {VSDBGSH_FILE_NAME} to {}", host.name());
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    ctrlc::set_handler(move || _ = tx.send(()))?;
+    let login = host.login();
+    let mut socat = Command::new("tsh").args(COMMON_TSH_ARGS)
+                                       .args(["ssh",
+                                              &login,
+                                              &f!("sudo -n bash {VSDBGSH_FILE_NAME} {container} {port}")])
+                                       .run_spawn()?;
+    let tunnel = Tunnel::new(host.name(),
+                             host.platform().login(),
+                             Forward::Local,
+                             local.unwrap_or(*port),
+                             *port);
+    p!("Tunneling {} ...", tunnel.describe());
+    let mut forward = tunnel.command().run_spawn()?;
+    let remove_socat = f!("{}d rm -f tt-vsdbg-{port}", host.platform().docker());
+    if process::is_dry_run() {
+        // no-ops stand in for socat and the forward, waiting on them would only see them exit
+        return tsh_change(&host, &remove_socat);
+    }
+    p!("Press Ctrl-C to stop");
+    let stopped = loop {
+        if rx.recv_timeout(std::time::Duration::from_secs(1)).is_ok() {
+            break None;
+        }
+        if let Some(status) = socat.try_wait()? {
+            break Some(f!("socat tunnel exited with {status}"));
+        }
+        if let Some(status) = forward.try_wait()? {
+            break Some(f!("port forward exited with {status}"));
+        }
+    };
+    _ = forward.kill();
+    _ = socat.kill();
+    tsh_change(&host, &remove_socat)?;
+    match stopped {
+        Some(reason) => bail!("{reason}, stopped"),
+        None => {
+            p!("Stopped");
+            Ok(())
+        }
+    }
+}
+
+pub fn debug_config(s: &Settings, DebugConfigArgs { container, name, debugger, file }: &DebugConfigArgs) -> Result<()> {
+    let host = select_host(s)?;
+    let container = container.as_deref().map(|x| select_container(&host, x)).transpose()?;
+    let name = name.clone().unwrap_or_else(|| match &container {
+                               Some(container) => f!("Attach {}/{container}", host.name()),
+                               None => f!("Attach {}", host.name()),
+                           });
+    let default = if container.is_some() { launch::CONTAINER_DEBUGGER } else { launch::HOST_DEBUGGER };
+    let config = launch::attach_config(&name,
+                                       &host,
+                                       container.as_deref(),
+                                       debugger.as_deref().unwrap_or(default));
+    let text = file.exists().then(|| std::fs::read_to_string(file)).transpose()?;
+    let merged = launch::merge(text.as_deref(), config).wrap_err_with(|| f!("can't parse {}", file.display()))?;
+    if let Some(dir) = file.parent().filter(|x| !x.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(file, merged)?;
+    p!("Wrote '{name}' to {}", file.display());
     Ok(())
 }
 
-pub fn code(s: &Settings) -> Result<()> {
-    append_tsh_to_ssh_config()?;
-    let name = &select_host(s)?.ssh_name();
-    Command::new(&s.code_cmd).args(["--folder-uri",
-                                    &f!("vscode-remote://ssh-remote+ubuntu@{name}/home/ubuntu")])
-                             .status()?;
+pub fn paths(s: &Settings) -> Result<()> {
+    let paths = [("config", &s.config_path),
+                 ("vsdbg script", &s.vsdbgsh_path),
+                 ("host cache", &s.cache_path),
+                 ("ping status", &s.status_path),
+                 ("history", &s.history_path),
+                 ("folder history", &s.folder_history_path),
+                 ("tunnels", &s.tunnels_path),
+                 ("transcripts", &s.transcripts_dir)];
+    let rows = paths.iter().map(|(name, path)| vec![name.to_string(), path.display().to_string()]).collect_vec();
+    p!("{}", table(&["WHAT", "PATH"], &rows));
+    Ok(())
+}
+
+pub fn logs(s: &Settings, list: bool) -> Result<()> {
+    let mut logs = vec![];
+    if s.transcripts_dir.exists() {
+        for host_dir in std::fs::read_dir(&s.transcripts_dir)?.filter_map(Result::ok) {
+            let host = host_dir.file_name().to_string_lossy().into_owned();
+            for log in std::fs::read_dir(host_dir.path())?.filter_map(Result::ok) {
+                // raw recordings of sessions still running aren't transcripts yet
+                if log.path().extension().is_some_and(|x| x == "log") {
+                    logs.push((log.file_name().to_string_lossy().into_owned(), host.clone(), log.path()));
+                }
+            }
+        }
+    }
+    ensure!(!logs.is_empty(), "No transcripts in {}", s.transcripts_dir.display());
+    logs.sort_by(|a, b| b.cmp(a));
+    if list {
+        logs.iter().for_each(|(_, _, path)| p!("{}", path.display()));
+        return Ok(());
+    }
+    let width = logs.iter().map(|(_, host, _)| host.len()).max().unwrap_or_default();
+    let options =
+        logs.iter().map(|(file, host, _)| f!("{host:width$} {}", file.trim_end_matches(".log"))).collect_vec();
+    let idx = select::select("", &options, &s.start_value)?;
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "less" }.into());
+    Command::new(pager).arg(&logs[idx].2).run()?;
     Ok(())
 }
 
+pub fn run(s: &Settings, snippet: &Option<String>) -> Result<()> {
+    let snippets = &s.config.snippets;
+    let (host, snippet) = match snippet {
+        Some(name) => {
+            let snippet = snippets.get(name).ok_or_else(|| eyre!("No snippet named '{name}'"))?;
+            (select_host_where(s, |h| snippet.applies_to(h))?, snippet)
+        }
+        None => {
+            let host = select_host(s)?;
+            let names = snippets.iter().filter(|(_, x)| x.applies_to(&host)).map(|(name, _)| name).collect_vec();
+            ensure!(!names.is_empty(), "No snippet applies to {}", host.name());
+            let width = names.iter().map(|x| x.len()).max().unwrap_or_default();
+            let options = names.iter().map(|name| f!("{name:width$} {}", snippets[*name].command)).collect_vec();
+            let idx = select::select("", &options, "")?;
+            (host, &snippets[names[idx]])
+        }
+    };
+    let command = host.render(&snippet.command, |x| host.platform().quote(x))?;
+    p!("{}: {command}", host.name());
+    tsh_exec(s, &host, &command)
+}
+
+pub fn code(s: &Settings, path: &Option<String>) -> Result<()> {
+    ssh_config::ensure_installed(&s.login_path)?;
+    let host = select_host(s)?;
+    let platform = host.platform();
+    let path = match path {
+        Some(path) => platform.absolute(path),
+        None => select_remote_folder(s, &host)?,
+    };
+    FolderHistory::load(&s.folder_history_path)?.update(&host, &path)?;
+    let kind = if path.ends_with(".code-workspace") { "--file-uri" } else { "--folder-uri" };
+    let uri = f!("vscode-remote://ssh-remote+{}{}",
+                 host.ssh_login(),
+                 platform.uri_path(&path));
+    Command::new(&s.code_cmd).args([kind, &uri]).run()?;
+    Ok(())
+}
+
+fn select_remote_folder(s: &Settings, host: &Host) -> Result<String> {
+    const BROWSE: &str = "Browse...";
+    let recents = FolderHistory::load(&s.folder_history_path)?.get(host);
+    if recents.is_empty() {
+        return browse_remote(host, true);
+    }
+    let choice = select::select_str("", &[recents, vec![BROWSE.into()]].concat(), "")?;
+    if choice == BROWSE {
+        browse_remote(host, true)
+    } else {
+        Ok(choice)
+    }
+}
+
 // pub fn win_event_log(hosts: &Hosts) -> Result<()> {
 //     let host_name = &select_profile_then_host(hosts)?;
 //     if hosts.hosts[host_name].platform != Platform::Win {
@@ -237,20 +1276,142 @@ pub fn code(s: &Settings) -> Result<()> {
 //     Ok(())
 // }
 
-pub fn append_tsh_to_ssh_config() -> Result<()> {
-    let ssh_config =
-        directories::UserDirs::new().context("can't retrieve home directory")?.home_dir().join(".ssh").join("config");
-    if read_to_string(&ssh_config)?.contains("# Begin generated Teleport configuration") {
-        return Ok(());
+pub fn config(s: &Settings, action: &Option<ConfigCommands>) -> Result<()> {
+    match action.as_ref().unwrap_or(&ConfigCommands::Install) {
+        ConfigCommands::Install => ssh_config::install(&s.login_path),
+        ConfigCommands::Update => ssh_config::update(&s.login_path),
+        ConfigCommands::Diff => ssh_config::diff(&s.login_path),
+        ConfigCommands::Remove => ssh_config::remove(),
+        ConfigCommands::Show => {
+            // loaded again, settings fall back to defaults when the file is broken
+            p!("{}", serde_json::to_string_pretty(&Config::load(&s.config_path)?)?);
+            Ok(())
+        }
+        ConfigCommands::Edit => edit_config(s),
+        ConfigCommands::Validate { file } => {
+            let path = file.as_ref().unwrap_or(&s.config_path);
+            let text = std::fs::read_to_string(path).wrap_err_with(|| f!("can't read {}", path.display()))?;
+            Config::validate(&text).wrap_err_with(|| f!("{} is invalid", path.display()))?;
+            p!("{} is valid", path.display());
+            Ok(())
+        }
+        ConfigCommands::Schema => {
+            p!("{}", serde_json::to_string_pretty(&Config::schema())?);
+            Ok(())
+        }
+    }
+}
+
+/// $VISUAL or $EDITOR, which may carry arguments, e.g. 'code --wait'
+fn run_editor(path: &Path) -> Result<ExitStatus> {
+    let editor =
+        std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR"))
+                               .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.into());
+    // backslashes separate Windows paths rather than escape
+    let escaped = if cfg!(windows) { editor.replace('\\', "\\\\") } else { editor.clone() };
+    // quoted like in a shell, e.g. "'/Applications/Sublime Text.app/Contents/SharedSupport/bin/subl' -w"
+    let words = shell_words::split(&escaped).wrap_err_with(|| f!("invalid editor {editor}"))?;
+    let (program, args) = words.split_first().context("empty $EDITOR")?;
+    Ok(Command::new(program).args(args).arg(path).run()?)
+}
+
+/// Edits a copy, replacing the config only once it validates
+fn edit_config(s: &Settings) -> Result<()> {
+    let original = match std::fs::read_to_string(&s.config_path) {
+        Ok(text) => text,
+        Err(_) => serde_json::to_string_pretty(&Config::default())?,
+    };
+    let mut dir = TempDir::new("tt-config")?;
+    let local = dir.path.join(CONFIG_FILE_NAME);
+    std::fs::write(&local, &original)?;
+    loop {
+        let status = run_editor(&local)?;
+        dir.keep = !status.success();
+        ensure!(status.success(),
+                "editor exited with {status}, config left untouched, your copy is in {}",
+                local.display());
+        let text = std::fs::read_to_string(&local)?;
+        if text == original {
+            p!("No changes");
+            break;
+        }
+        match Config::validate(&text) {
+            Ok(_) => {
+                std::fs::write(&s.config_path, text)?;
+                p!("Saved {}", s.config_path.display());
+                break;
+            }
+            Err(err) => {
+                eprintln!("Invalid config: {err:#}");
+                if !dialoguer::Confirm::new().with_prompt("Edit again?").default(true).interact()? {
+                    dir.keep = true;
+                    bail!("config left untouched, your copy is in {}", local.display());
+                }
+            }
+        }
     }
-    let config = Command::new("tsh").args(COMMON_TSH_ARGS).args(["config"]).output()?.stdout;
-    let mut f = std::fs::OpenOptions::new().write(true).append(true).open(&ssh_config)?;
-    f.write_all(&config)?;
     Ok(())
 }
 
+pub fn alias(s: &Settings, action: &AliasCommands) -> Result<()> {
+    let mut config = s.config.clone();
+    match action {
+        AliasCommands::Add { name, host } => {
+            ensure!(!is_command(name),
+                    "'{name}' is a tt command, 'tt {name}' would never reach the alias");
+            let host = select_or_find_host(s, host)?;
+            p!("{name} -> {} [{}]", host.name(), host.key());
+            config.aliases.insert(name.to_owned(), host.key().to_owned());
+        }
+        AliasCommands::Rm { name } => {
+            config.aliases.remove(name).ok_or_else(|| eyre!("No alias named '{name}'"))?;
+        }
+        AliasCommands::Ls => {
+            let width = config.aliases.keys().map(|x| x.len()).max().unwrap_or_default();
+            config.aliases.iter().for_each(|(name, key)| p!("{name:width$} -> {key}"));
+            return Ok(());
+        }
+    }
+    config.save(&s.config_path)
+}
+
+/// Whether `tt <name>` runs a subcommand
+fn is_command(name: &str) -> bool {
+    let mut tt = AshArgs::command();
+    // builds the implicit `help` subcommand
+    tt.build();
+    tt.find_subcommand(name).is_some()
+}
+
+pub fn fav(s: &Settings, action: &FavCommands) -> Result<()> {
+    let mut config = s.config.clone();
+    match action {
+        FavCommands::Add { host } => {
+            let host = select_or_find_host(s, host)?;
+            if config.favorites.iter().any(|k| k == host.key()) {
+                return Ok(());
+            }
+            p!("{} {} [{}]", select::FAVORITE_GLYPH, host.name(), host.key());
+            config.favorites.push(host.key().to_owned());
+        }
+        FavCommands::Rm { host } => {
+            ensure!(config.favorites.contains(host), "{host} is not a favorite");
+            config.favorites.retain(|k| k != host);
+        }
+        FavCommands::Ls => {
+            let hosts = get_hosts(s)?;
+            for key in &config.favorites {
+                let name = hosts.iter().find(|h| h.key() == key).map(|h| h.name()).unwrap_or("?");
+                p!("{name} [{key}]");
+            }
+            return Ok(());
+        }
+    }
+    config.save(&s.config_path)
+}
+
 pub fn get_file(s: &Settings, file: &Option<String>) -> Result<()> {
-    append_tsh_to_ssh_config()?;
+    ssh_config::ensure_installed(&s.login_path)?;
     let host = select_host(s)?;
     let path = if let Some(file) = file { file.to_owned() } else { browse_local(s)? };
     scp_execute(&path, ".")?;
@@ -258,10 +1419,10 @@ pub fn get_file(s: &Settings, file: &Option<String>) -> Result<()> {
 }
 
 pub fn put_file(s: &Settings, file: &Option<String>) -> Result<()> {
-    append_tsh_to_ssh_config()?;
+    ssh_config::ensure_installed(&s.login_path)?;
     let path = if let Some(file) = file { file.to_owned() } else { browse_local(s)? };
     let host = select_host(s)?;
-    scp_execute(&path, &f!("ubuntu@{}.aws:", &host.name()))?;
+    scp_execute(&path, &f!("{}:", host.ssh_login()))?;
     Ok(())
 }
 
@@ -286,28 +1447,31 @@ fn browse_local(s: &Settings) -> Result<String> {
     }
 }
 
-fn browse_remote(host: &Host) -> Result<String> {
-    let host_name = f!("ubuntu@{}.aws", &host.name());
-    let mut ssh = Ssh::new(&host_name)?;
-    ssh.write("pwd")?;
+/// Browses the remote filesystem and returns the absolute path of the chosen file, or folder if `pick_dir`
+/// (select './' to pick the current folder, .code-workspace files can be picked as well)
+fn browse_remote(host: &Host, pick_dir: bool) -> Result<String> {
+    let platform = host.platform();
+    let mut ssh = Ssh::new(&host.ssh_login(), platform.shell())?;
+    ssh.write(platform.pwd())?;
     let mut base_dir = ssh.read()?;
     loop {
-        ssh.write(&f!("ls --group-directories-first -pa1 {base_dir}"))?;
+        ssh.write(&platform.list_dir(&base_dir))?;
         let out = ssh.read()?;
         let entries = parse_ls_output(&out, &base_dir)?;
-        let options = entries.iter().map(|x| x.file_name.clone()).filter(|x| x != "./").collect_vec();
+        let options = entries.iter().map(|x| x.file_name.clone()).filter(|x| pick_dir || x != "./").collect_vec();
         let file = select::select_str("", &options, "")?;
         let entry = entries.iter().find(|x| x.file_name == file).unwrap().clone();
+        if pick_dir && entry.file_name == "./" {
+            return Ok(base_dir);
+        }
         if entry.is_dir {
             if entry.file_name == "../" {
-                if let Some(parent) = Path::new(&base_dir).parent() {
-                    base_dir = parent.to_string_lossy().into_owned();
-                }
+                base_dir = platform.parent(&base_dir);
             } else {
-                base_dir = f!("{base_dir}/{}", entry.file_name)
+                base_dir = platform.join(&base_dir, &entry.file_name)
             }
-        } else {
-            return Ok(f!("{host_name}:{base_dir}/{file}"));
+        } else if !pick_dir || file.ends_with(".code-workspace") {
+            return Ok(platform.join(&base_dir, &file));
         }
     }
 }
@@ -432,7 +1596,7 @@ pub fn read_dir(path: impl AsRef<Path>) -> Result<Vec<Entry>> {
 // }
 
 fn scp_execute(from: &str, to: &str) -> std::io::Result<ExitStatus> {
-    Command::new("scp").args(["-T"]).args([from, to]).status()
+    Command::new("scp").args(["-T"]).args([from, to]).run()
 }
 
 #[derive(Debug, Clone)]
@@ -492,4 +1656,66 @@ test.txt
         assert!(res.is_ok());
         println!("{:#?}", res.unwrap());
     }
+
+    #[test]
+    fn args_are_consistent() {
+        AshArgs::command().debug_assert();
+    }
+
+    #[test]
+    fn aliases_resolve_directly_unless_broken() {
+        let hosts = [Host::test("k1", "db-primary", &[]), Host::test("k2", "web-1", &[])];
+        let aliases = BTreeMap::from([("db1".to_owned(), "k1".to_owned()),
+                                      ("old".to_owned(), "gone".to_owned())]);
+        assert_eq!(direct_host(&hosts, &aliases, "db1").unwrap(), Some(0));
+        assert_eq!(direct_host(&hosts, &aliases, "k2").unwrap(), Some(1));
+        assert_eq!(direct_host(&hosts, &aliases, "web").unwrap(), None);
+        assert!(direct_host(&hosts, &aliases, "old").is_err());
+        assert!(is_command("exec") && is_command("help") && !is_command("db1"));
+    }
+
+    #[test]
+    fn favorites_come_first_in_their_order() {
+        let hosts = ["a", "b", "c", "d"].map(|k| Host::test(k, k, &[])).to_vec();
+        let favorites = ["c".to_owned(), "gone".to_owned(), "a".to_owned()];
+        let keys = add_favorites(hosts, &favorites).iter().map(|h| h.key().to_owned()).collect_vec();
+        assert_eq!(keys, ["c", "a", "b", "d"]);
+    }
+
+    #[test]
+    fn pick_output_renders_fields_and_templates() {
+        let hosts = [Host::test("k1", "db-primary", &[("cluster", "aws")]),
+                     Host::test("k2", "web-1", &[])];
+        let pick = |hosts: &[Host], template: &str, null: bool| pick_output(hosts, template, null).unwrap();
+        assert_eq!(pick(&hosts, PickField::Hostname.template(), false),
+                   "db-primary\nweb-1\n");
+        assert_eq!(pick(&hosts[..1], PickField::SshName.template(), false),
+                   "db-primary.aws\n");
+        assert_eq!(pick(&hosts[..1], PickField::Addr.template(), false), "10.0.0.1:3022\n");
+        assert_eq!(pick(&hosts, PickField::Key.template(), true), "k1\0k2\0");
+        assert_eq!(pick(&hosts[..1], "{key}={label:cluster}", true), "k1=aws\0");
+        assert!(pick_output(&hosts, "{key}={label:cluster}", true).is_err());
+    }
+
+    #[test]
+    fn on_hosts_caps_sessions_and_keeps_order() {
+        let hosts = (0..20).map(|i| Host::test(&f!("k{i}"), &f!("web-{i}"), &[])).collect_vec();
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let keys = on_hosts(&hosts, |h| {
+            most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            running.fetch_sub(1, Ordering::SeqCst);
+            h.key().to_owned()
+        });
+        assert_eq!(keys, hosts.iter().map(|h| h.key()).collect_vec());
+        assert_eq!(most.into_inner(), MAX_SESSIONS);
+    }
+
+    #[test]
+    fn split_remote_keeps_drive_letters_local() {
+        assert_eq!(split_remote("web-1:/srv"), Some(("web-1", "/srv")));
+        assert_eq!(split_remote(":/srv"), Some(("", "/srv")));
+        assert_eq!(split_remote("C:\\src"), None);
+        assert_eq!(split_remote("./src"), None);
+    }
 }
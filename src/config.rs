@@ -0,0 +1,235 @@
+use crate::prelude::*;
+use crate::teleport::Host;
+use itertools::Itertools;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Bumped with every change needing `migrate`
+pub const CONFIG_VERSION: u64 = 1;
+/// Settings of the ssh based predecessor found in the first tt.config.json, none read anymore
+const LEGACY_KEYS: &[&str] = &["keys_path", "bastion_name", "update", "merge_profiles"];
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Shape of this file, older ones are migrated when loaded
+    pub version: u64,
+    /// Short names resolving straight to a host key, e.g. `tt db1`
+    pub aliases: BTreeMap<String, String>,
+    /// Host keys always listed first in the host picker
+    pub favorites: Vec<String>,
+    /// Named remote commands, run with `tt run <name>`
+    pub snippets: BTreeMap<String, Snippet>,
+    /// Session transcripts, see `tt logs`
+    pub transcripts: TranscriptsConfig,
+    /// `tt socks` settings
+    pub socks: SocksConfig,
+    /// Host selector settings
+    pub picker: PickerConfig,
+    /// Label telling whether a node runs Linux or Windows, `os` if omitted
+    pub platform_label: Option<String>,
+    /// Url or path of the release manifest read by `--check-update`
+    pub release_manifest: Option<String>,
+    /// Keys tt doesn't know about, kept as they are when saving
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, Value>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { version: CONFIG_VERSION,
+               aliases: Default::default(),
+               favorites: Default::default(),
+               snippets: Default::default(),
+               transcripts: Default::default(),
+               socks: Default::default(),
+               picker: Default::default(),
+               platform_label: None,
+               release_manifest: None,
+               other: Default::default() }
+    }
+}
+
+impl Config {
+    /// Migrates an older file in place, keeping the original next to it as `.bak`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)?;
+        let (config, from) = Self::parse(&text).wrap_err_with(|| f!("Error deserializing config {}", path.display()))?;
+        if from < CONFIG_VERSION {
+            let backup = path.with_extension("json.bak");
+            std::fs::write(&backup, &text)?;
+            config.save(path)?;
+            eprintln!("Migrated {} to version {CONFIG_VERSION}, the previous one is in {}",
+                      path.display(),
+                      backup.display());
+        }
+        Ok(config)
+    }
+
+    /// The config and the version it was written with
+    pub fn parse(text: &str) -> Result<(Self, u64)> {
+        let mut value = serde_json::from_str(text)?;
+        let from = migrate(&mut value)?;
+        Ok((serde_json::from_value(value)?, from))
+    }
+
+    /// Like `parse`, also failing on keys `schema` doesn't allow and invalid regexes
+    pub fn validate(text: &str) -> Result<Self> {
+        let mut value = serde_json::from_str(text)?;
+        migrate(&mut value)?;
+        let config: Config = serde_json::from_value(value.clone())?;
+        let schema = Self::schema();
+        let unknown = unknown_keys(&value, &schema, &schema, "");
+        ensure!(unknown.is_empty(), "unknown keys: {}", unknown.join(", "));
+        for re in &config.transcripts.redact {
+            regex::Regex::new(re).wrap_err_with(|| f!("invalid transcripts.redact regex '{re}'"))?;
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// JSON schema of the file, for `tt config validate` and editors. `other` only keeps what a newer tt wrote, so
+    /// no object allows keys beyond its properties
+    pub fn schema() -> Value {
+        let mut schema = serde_json::to_value(schemars::schema_for!(Config)).expect("schema serializes");
+        deny_additional_properties(&mut schema);
+        schema
+    }
+}
+
+fn deny_additional_properties(schema: &mut Value) {
+    match schema {
+        Value::Object(object) => {
+            if object.contains_key("properties") {
+                object.insert("additionalProperties".into(), false.into());
+            }
+            object.values_mut().for_each(deny_additional_properties);
+        }
+        Value::Array(items) => items.iter_mut().for_each(deny_additional_properties),
+        _ => {}
+    }
+}
+
+/// Brings `value` to `CONFIG_VERSION`, returning the version it had
+fn migrate(value: &mut Value) -> Result<u64> {
+    let config = value.as_object_mut().context("config is not an object")?;
+    let from = match config.get("version") {
+        Some(version) => version.as_u64().context("version is not a number")?,
+        None => 0,
+    };
+    ensure!(from <= CONFIG_VERSION,
+            "config version {from} is newer than {CONFIG_VERSION}, update tt");
+    if from == 0 {
+        LEGACY_KEYS.iter().for_each(|x| _ = config.remove(*x));
+    }
+    config.insert("version".into(), CONFIG_VERSION.into());
+    Ok(from)
+}
+
+/// Dotted paths of the object keys in `value` that `schema` doesn't allow, `$ref`s resolved in `root`
+fn unknown_keys(value: &Value, schema: &Value, root: &Value, prefix: &str) -> Vec<String> {
+    if let Some(name) = schema["$ref"].as_str().and_then(|x| x.strip_prefix("#/definitions/")) {
+        return unknown_keys(value, &root["definitions"][name], root, prefix);
+    }
+    // schemars wraps documented `$ref`s in an `allOf`
+    if let Some(schemas) = schema["allOf"].as_array() {
+        return schemas.iter().flat_map(|x| unknown_keys(value, x, root, prefix)).unique().collect();
+    }
+    let field = |k: &str, v: &Value| match (schema["properties"].get(k), &schema["additionalProperties"]) {
+        (Some(property), _) => unknown_keys(v, property, root, &f!("{prefix}{k}.")),
+        (None, Value::Bool(false)) => vec![f!("{prefix}{k}")],
+        // a map, e.g. `aliases`, with its values' schema if any
+        (None, additional) => unknown_keys(v, additional, root, &f!("{prefix}{k}.")),
+    };
+    match value {
+        Value::Object(object) => object.iter().flat_map(|(k, v)| field(k, v)).collect(),
+        Value::Array(items) => items.iter()
+                                    .enumerate()
+                                    .flat_map(|(i, v)| unknown_keys(v, &schema["items"], root, &f!("{prefix}{i}.")))
+                                    .collect(),
+        _ => vec![],
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct TranscriptsConfig {
+    /// Record every ssh and exec session, same as always passing `--record`
+    pub enabled: bool,
+    /// Extra regexes whose matches are masked, on top of the built-in password/token/key ones
+    pub redact: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SocksConfig {
+    /// Domains routed through the proxy by the generated PAC file, e.g. `corp.internal`
+    pub domains: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(default)]
+pub struct PickerConfig {
+    /// Show the latency or `down` from the last `tt ping` next to each host
+    pub reachability: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct Snippet {
+    /// Remote command, may contain host variables, shell quoted, e.g. `journalctl -u {label:service} -n 100`
+    pub command: String,
+    /// Labels a host must carry for the snippet to apply, `*` matches any value
+    #[serde(default)]
+    pub selector: BTreeMap<String, String>,
+}
+
+impl Snippet {
+    pub fn applies_to(&self, host: &Host) -> bool {
+        host.matches(&self.selector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_legacy_and_reject_unknown_keys() {
+        let legacy = r#"{ "keys_path": "~/.ssh", "bastion_name": "", "update": false, "merge_profiles": false }"#;
+        let (config, from) = Config::parse(legacy).unwrap();
+        assert_eq!((from, config.version), (0, CONFIG_VERSION));
+        assert!(config.other.is_empty());
+        assert!(Config::validate(r#"{ "version": 1, "picker": { "reachability": true } }"#).is_ok());
+        let err = Config::validate(r#"{ "version": 1, "picker": { "reachabilty": true }, "x": 1 }"#).unwrap_err();
+        assert_eq!(err.to_string(), "unknown keys: picker.reachabilty, x");
+        let snippets = r#"{ "aliases": { "db": "k1" }, "snippets": { "logs": { "command": "ls", "selectr": {} } } }"#;
+        assert_eq!(Config::validate(snippets).unwrap_err().to_string(),
+                   "unknown keys: snippets.logs.selectr");
+        assert!(Config::parse(r#"{ "version": 99 }"#).is_err());
+    }
+
+    #[test]
+    fn schema_allows_exactly_the_keys_serde_knows() {
+        let schema = Config::schema();
+        let known = serde_json::to_value(Config::default()).unwrap();
+        let properties = |x: &Value| x["properties"].as_object().unwrap().keys().cloned().collect_vec();
+        assert_eq!(properties(&schema),
+                   known.as_object().unwrap().keys().cloned().collect_vec());
+        assert_eq!(schema["additionalProperties"], false);
+        for name in ["TranscriptsConfig", "SocksConfig", "PickerConfig", "Snippet"] {
+            assert_eq!(schema["definitions"][name]["additionalProperties"], false, "{name}");
+        }
+        assert_ne!(schema["properties"]["aliases"]["additionalProperties"], false);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::prelude::*;
+use regex::Regex;
+use std::io::{ErrorKind, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// Teleport login failed or the host denied the login
+pub const AUTH: i32 = 77;
+/// tsh couldn't reach the host, as ssh does
+pub const TRANSPORT: i32 = 255;
+/// tsh's own failures exit 1 after a line like `ERROR: access denied to ubuntu connecting to web-1`
+const TSH_FAILURE: i32 = 1;
+const AUTH_ERRORS: &[&str] = &[r"access denied to \S+ connecting to ",
+                               "not logged in",
+                               r".*\bcert(ificate)? has expired",
+                               "ssh: handshake failed: ssh: unable to authenticate"];
+const TRANSPORT_ERRORS: &[&str] = &["failed connecting to node",
+                                    r".*\bdial tcp ",
+                                    r".*\bconnection refused",
+                                    r".*\bi/o timeout",
+                                    "ssh: handshake failed: EOF"];
+
+pub const HELP: &str = "\
+Exit codes:
+  0-255  the remote command's own exit code (ssh, exec, run)
+  1      tt failed, e.g. no host found or selection cancelled
+  2      invalid arguments
+  77     Teleport login failed or the host denied access
+  255    the host couldn't be reached
+
+tsh failures are told apart by the error tsh prints before exiting 1. A remote command exiting 1
+after printing the same message, or exiting 77 or 255 itself, can't be told from them.";
+
+/// Error ending tt with `code`, printing `message` unless it's empty
+#[derive(Debug)]
+pub struct Exit {
+    pub code: i32,
+    pub message: String,
+}
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Exit {}
+
+/// Runs `cmd` forwarding its stderr as it comes, prompts included, returning the last non empty
+/// stderr line for `check`
+pub fn status(cmd: &mut Command) -> Result<(ExitStatus, Option<String>)> {
+    let mut child = cmd.stderr(Stdio::piped()).run_spawn()?;
+    let mut stderr = child.stderr.take().context("can't take stderr")?;
+    let (mut buf, mut pending, mut last) = ([0; 4096], vec![], None);
+    loop {
+        let n = match stderr.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        std::io::stderr().write_all(&buf[..n])?;
+        pending.extend_from_slice(&buf[..n]);
+        if let Some(end) = pending.iter().rposition(|x| *x == b'\n') {
+            last = last_line(&pending[..end]).or(last);
+            pending.drain(..=end);
+        }
+    }
+    Ok((child.wait()?, last_line(&pending).or(last)))
+}
+
+fn last_line(bytes: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(bytes).lines().map(str::trim).rfind(|x| !x.is_empty()).map(String::from)
+}
+
+/// Fails with the remote command's exit code, or `AUTH`/`TRANSPORT` when the last stderr line
+/// is one of tsh's own connection errors
+pub fn check(status: ExitStatus, last_stderr: Option<&str>) -> Result<()> {
+    if status.success() {
+        return Ok(());
+    }
+    let code = status.code().unwrap_or(1);
+    let is = |errors: &[&str]| {
+        let re = Regex::new(&f!("(?i)^ERROR: ({})", errors.join("|"))).expect("invalid tsh error pattern");
+        code == TSH_FAILURE && last_stderr.is_some_and(|x| re.is_match(x))
+    };
+    let code = if is(AUTH_ERRORS) {
+        AUTH
+    } else if is(TRANSPORT_ERRORS) {
+        TRANSPORT
+    } else {
+        code
+    };
+    // tsh or the remote command already explained the failure
+    Err(Exit { code, message: String::new() }.into())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    fn code(raw: i32, stderr: Option<&str>) -> i32 {
+        check(ExitStatus::from_raw(raw << 8), stderr).unwrap_err().downcast_ref::<Exit>().unwrap().code
+    }
+
+    #[test]
+    fn check_tells_tsh_errors_from_remote_codes() {
+        assert!(check(ExitStatus::from_raw(0), None).is_ok());
+        assert_eq!(code(3, Some("ERROR: disk full")), 3);
+        assert_eq!(code(1, Some("ls: cannot access 'x'")), 1);
+        assert_eq!(code(1, Some("ERROR: access denied to ubuntu connecting to web-1")),
+                   AUTH);
+        assert_eq!(code(1, Some("ERROR: failed connecting to node web-1")), TRANSPORT);
+        assert_eq!(code(1, Some("ERROR: disk full")), 1);
+        assert_eq!(code(1, Some("ERROR: permission denied writing /var/log")), 1);
+        assert_eq!(code(3, Some("ERROR: access denied to ubuntu connecting to web-1")), 3);
+        assert_eq!(code(255, None), 255);
+    }
+}
@@ -1,36 +1,71 @@
+use crate::prelude::*;
+use crate::state;
 use crate::teleport::{Host, Hosts};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize)]
+const MAX_FOLDERS_PER_HOST: usize = 10;
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct History {
     pub(crate) entries: Vec<Host>,
+    #[serde(skip)]
     path: PathBuf,
 }
 
 impl History {
-    pub fn load(path: impl AsRef<Path>) -> Self {
-        if !path.as_ref().exists() {
-            History { path: path.as_ref().to_path_buf(), entries: Default::default() }.save();
-        }
-        let h = std::fs::File::open(path).expect("can't load history");
-        serde_json::from_reader(h).expect("Error deserializing history")
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Ok(Self { path: path.to_owned(), ..state::load(path)? })
     }
 
-    pub fn update(mut self, host: &Host) -> Self {
+    pub fn update(mut self, host: &Host) -> Result<Self> {
         self.entries.retain(|x| x.metadata.name != host.metadata.name);
         self.entries.insert(0, host.to_owned());
-        self.save();
-        self
+        self.save()?;
+        Ok(self)
     }
 
-    pub fn intersect(mut self, hosts: &Hosts) -> Self {
+    pub fn intersect(mut self, hosts: &Hosts) -> Result<Self> {
         self.entries.retain(|x| hosts.iter().any(|y| y.metadata.name == x.metadata.name));
-        self.save();
-        self
+        self.save()?;
+        Ok(self)
+    }
+
+    pub(crate) fn save(&self) -> Result<()> {
+        state::save(&self.path, self)
+    }
+}
+
+/// Remote folders recently opened with `tt code`, by host key
+#[derive(Serialize, Deserialize, Default)]
+pub struct FolderHistory {
+    pub(crate) entries: BTreeMap<String, Vec<String>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl FolderHistory {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Ok(Self { path: path.to_owned(), ..state::load(path)? })
+    }
+
+    pub fn get(&self, host: &Host) -> Vec<String> {
+        self.entries.get(host.key()).cloned().unwrap_or_default()
+    }
+
+    pub fn update(mut self, host: &Host, folder: &str) -> Result<Self> {
+        let folders = self.entries.entry(host.key().to_owned()).or_default();
+        folders.retain(|x| x != folder);
+        folders.insert(0, folder.to_owned());
+        folders.truncate(MAX_FOLDERS_PER_HOST);
+        self.save()?;
+        Ok(self)
     }
 
-    pub(crate) fn save(&self) {
-        std::fs::write(&self.path, serde_json::to_string(self).unwrap()).unwrap();
+    pub(crate) fn save(&self) -> Result<()> {
+        state::save(&self.path, self)
     }
 }
@@ -0,0 +1,99 @@
+use crate::platform::Platform;
+use crate::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+
+const LINUX: &str = r#"echo "uptime=$(uptime -p 2>/dev/null || uptime)"
+echo "load=$(cut -d' ' -f1-3 /proc/loadavg)"
+echo "memory=$(free -m | awk '/^Mem:/{print $3"/"$2" MiB"}')"
+echo "disk=$(df -h / | awk 'NR==2{print $3"/"$2" ("$5")"}')"
+echo "os=$(. /etc/os-release && echo $PRETTY_NAME)"
+echo "containers=$( (docker ps -q 2>/dev/null || sudo -n docker ps -q 2>/dev/null) | wc -l)""#;
+
+const WINDOWS: &str = r#"powershell -NoProfile -Command "$o = Get-CimInstance Win32_OperatingSystem; $d = Get-CimInstance Win32_LogicalDisk -Filter 'DeviceID=''C:'''; 'uptime=' + ((Get-Date) - $o.LastBootUpTime).ToString('d\.hh\:mm'); 'load=' + (Get-CimInstance Win32_Processor | Measure-Object LoadPercentage -Average).Average + '%'; 'memory=' + [int](($o.TotalVisibleMemorySize - $o.FreePhysicalMemory) / 1KB) + '/' + [int]($o.TotalVisibleMemorySize / 1KB) + ' MiB'; 'disk=' + [int](($d.Size - $d.FreeSpace) / 1GB) + 'G/' + [int]($d.Size / 1GB) + 'G'; 'os=' + $o.Caption; 'containers=' + (docker ps -q | Measure-Object).Count""#;
+
+/// Remote command printing one `key=value` line per metric
+pub fn command(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Lnx => LINUX,
+        Platform::Win => WINDOWS,
+    }
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct NodeInfo {
+    pub host: String,
+    pub uptime: Option<String>,
+    pub load: Option<String>,
+    pub memory: Option<String>,
+    pub disk: Option<String>,
+    pub os: Option<String>,
+    pub containers: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl NodeInfo {
+    pub fn parse(host: &str, output: Result<String>) -> Self {
+        let output = match output {
+            Ok(output) => output,
+            Err(err) => {
+                let error = Some(err.to_string().trim().to_owned());
+                return Self { host: host.to_owned(), error, ..Default::default() };
+            }
+        };
+        let mut values = output.lines()
+                               .filter_map(|x| x.split_once('='))
+                               .map(|(k, v)| (k.trim(), v.trim().to_owned()))
+                               .filter(|(_, v)| !v.is_empty())
+                               .collect::<HashMap<_, _>>();
+        Self { host: host.to_owned(),
+               uptime: values.remove("uptime").map(|x| x.trim_start_matches("up ").to_owned()),
+               load: values.remove("load"),
+               memory: values.remove("memory"),
+               disk: values.remove("disk"),
+               os: values.remove("os"),
+               containers: values.remove("containers"),
+               error: None }
+    }
+
+    pub fn row(&self) -> Vec<String> {
+        if let Some(error) = &self.error {
+            return vec![self.host.clone(), f!("error: {error}")];
+        }
+        let value = |x: &Option<String>| x.clone().unwrap_or_else(|| "-".into());
+        vec![self.host.clone(),
+             value(&self.uptime),
+             value(&self.load),
+             value(&self.memory),
+             value(&self.disk),
+             value(&self.os),
+             value(&self.containers)]
+    }
+}
+
+pub const HEADER: &[&str] = &["HOST", "UPTIME", "LOAD", "MEMORY", "DISK", "OS", "CONTAINERS"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_key_values() {
+        const OUT: &str = r#"
+uptime=up 3 days, 2 hours
+load=0.10 0.20 0.30
+memory=1024/4096 MiB
+disk=
+os=Ubuntu 22.04.1 LTS
+containers=3
+"#;
+
+        let info = NodeInfo::parse("web-1", Ok(OUT.into()));
+        assert_eq!(info.uptime.as_deref(), Some("3 days, 2 hours"));
+        assert_eq!(info.disk, None);
+        assert_eq!(info.row()[5], "Ubuntu 22.04.1 LTS");
+        let info = NodeInfo::parse("web-2", Err(eyre!("connection refused")));
+        assert_eq!(info.row(), vec!["web-2", "error: connection refused"]);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::prelude::*;
+use crate::settings::COMMON_TSH_ARGS;
+use crate::teleport::Host;
+use serde::Serialize;
+use serde_json::ser::PrettyFormatter;
+use serde_json::{json, Value};
+
+/// vsdbg location after `res/vsdbg.sh` installed it into a container (as root)
+pub const CONTAINER_DEBUGGER: &str = "/root/vsdbg/vsdbg";
+pub const HOST_DEBUGGER: &str = "~/vsdbg/vsdbg";
+
+/// coreclr attach configuration running vsdbg on the host, or inside `container` through docker exec
+pub fn attach_config(name: &str, host: &Host, container: Option<&str>, debugger: &str) -> Value {
+    let mut pipe_args = COMMON_TSH_ARGS.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    pipe_args.extend(["ssh".into(), host.login()]);
+    if let Some(container) = container {
+        pipe_args.extend(host.platform().sudo().split_whitespace().map(String::from));
+        pipe_args.extend(["docker", "exec", "-i", container].map(String::from));
+    }
+    json!({
+        "name": name,
+        "type": "coreclr",
+        "request": "attach",
+        "processId": "${command:pickRemoteProcess}",
+        "pipeTransport": {
+            "pipeCwd": "${workspaceFolder}",
+            "pipeProgram": "tsh",
+            "pipeArgs": pipe_args,
+            "debuggerPath": debugger
+        }
+    })
+}
+
+/// Removes `//` and `/* */` comments and trailing commas, which VS Code accepts in launch.json
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|x| *x != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.next_if_eq(&'/').is_some() {
+                        break;
+                    }
+                }
+            }
+            ']' | '}' => {
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Significant piece of JSONC text by byte range, `kind` being the punctuation, `"` for strings or `v` for
+/// numbers and literals
+#[derive(Clone, Copy)]
+struct Token {
+    start: usize,
+    end: usize,
+    kind: char,
+}
+
+/// Tokens of `text` outside comments and whitespace
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => _ = chars.next(),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                '"'
+            }
+            '/' if chars.next_if(|x| x.1 == '/').is_some() => {
+                while chars.next_if(|x| x.1 != '\n').is_some() {}
+                continue;
+            }
+            '/' if chars.next_if(|x| x.1 == '*').is_some() => {
+                while let Some((_, c)) = chars.next() {
+                    if c == '*' && chars.next_if(|x| x.1 == '/').is_some() {
+                        break;
+                    }
+                }
+                continue;
+            }
+            '{' | '}' | '[' | ']' | ':' | ',' => c,
+            _ if c.is_whitespace() => continue,
+            _ => {
+                while chars.next_if(|x| !x.1.is_whitespace() && !"{}[]:,/\"".contains(x.1)).is_some() {}
+                'v'
+            }
+        };
+        let end = chars.peek().map(|x| x.0).unwrap_or(text.len());
+        tokens.push(Token { start, end, kind });
+    }
+    tokens
+}
+
+/// Index of the token after the value starting at `i`
+fn skip_value(tokens: &[Token], i: usize) -> usize {
+    let mut depth = 0;
+    for (j, token) in tokens.iter().enumerate().skip(i) {
+        match token.kind {
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            _ => {}
+        }
+        if depth <= 0 {
+            return j + 1;
+        }
+    }
+    tokens.len()
+}
+
+/// Where a configuration goes in the launch.json text
+enum Slot {
+    /// Byte ranges of the `configurations` elements and the bytes of its `[` and `]`
+    Array {
+        elements: Vec<(usize, usize)>,
+        open: usize,
+        close: usize,
+    },
+    /// No `configurations` key, members of the root object end at this byte, `comma` telling if one is needed
+    Missing { after: usize, comma: bool },
+}
+
+fn find_slot(text: &str) -> Option<Slot> {
+    let tokens = tokenize(text);
+    (tokens.first()?.kind == '{').then_some(())?;
+    let mut i = 1;
+    while tokens.get(i)?.kind == '"' {
+        let value = i + 2;
+        if &text[tokens[i].start..tokens[i].end] == "\"configurations\"" {
+            (tokens.get(value)?.kind == '[').then_some(())?;
+            let mut elements = vec![];
+            let mut j = value + 1;
+            while tokens.get(j)?.kind != ']' {
+                let end = skip_value(&tokens, j);
+                elements.push((tokens[j].start, tokens[end - 1].end));
+                j = if tokens.get(end)?.kind == ',' { end + 1 } else { end };
+            }
+            return Some(Slot::Array { elements, open: tokens[value].start, close: tokens[j].start });
+        }
+        i = skip_value(&tokens, value);
+        if tokens.get(i)?.kind == ',' {
+            i += 1;
+        }
+    }
+    let last = tokens.get(i.checked_sub(1)?)?;
+    Some(Slot::Missing { after: last.end, comma: !matches!(last.kind, '{' | ',') })
+}
+
+/// Leading whitespace of the line `pos` is on
+fn line_indent(text: &str, pos: usize) -> &str {
+    let line = &text[text[..pos].rfind('\n').map(|x| x + 1).unwrap_or(0)..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Adds `config` to the launch.json `text`, replacing the configuration with the same name if any.
+/// The rest of the text, comments included, stays as it is
+pub fn merge(text: Option<&str>, config: Value) -> Result<String> {
+    let text = match text {
+        Some(text) if !text.trim().is_empty() => text,
+        _ => {
+            let launch = json!({ "version": "0.2.0", "configurations": [config] });
+            return Ok(serde_json::to_string_pretty(&launch)? + "\n");
+        }
+    };
+    let launch: Value = serde_json::from_str(&strip_jsonc(text))?;
+    ensure!(launch.is_object(), "launch.json is not an object");
+    ensure!(launch.get("configurations").is_none_or(Value::is_array),
+            "configurations is not an array");
+    // VS Code indents launch.json by 4
+    let render = |indent: &str| -> Result<String> {
+        let mut out = vec![];
+        config.serialize(&mut serde_json::Serializer::with_formatter(&mut out, PrettyFormatter::with_indent(b"    ")))?;
+        Ok(String::from_utf8(out)?.replace('\n', &f!("\n{indent}")))
+    };
+    let spliced = match find_slot(text).context("can't find where configurations go in launch.json")? {
+        Slot::Array { elements, open, close } => {
+            let indent = f!("{}    ", line_indent(text, close));
+            let name = |(start, end): (usize, usize)| {
+                serde_json::from_str::<Value>(&strip_jsonc(&text[start..end])).ok().map(|x| x["name"].clone())
+            };
+            match elements.iter().find(|x| name(**x).as_ref() == Some(&config["name"])) {
+                Some(&(start, end)) => f!("{}{}{}", &text[..start], render(&indent)?, &text[end..]),
+                None => match elements.last() {
+                    Some(&(_, end)) => f!("{},\n{indent}{}{}", &text[..end], render(&indent)?, &text[end..]),
+                    None if close == open + 1 => {
+                        f!("{}\n{indent}{}\n{}{}",
+                           &text[..=open],
+                           render(&indent)?,
+                           line_indent(text, close),
+                           &text[close..])
+                    }
+                    None => f!("{}\n{indent}{}{}", &text[..=open], render(&indent)?, &text[open + 1..]),
+                },
+            }
+        }
+        Slot::Missing { after, comma } => {
+            let comma = if comma { "," } else { "" };
+            f!("{}{comma}\n    \"configurations\": [\n        {}\n    ]{}",
+               &text[..after],
+               render("        ")?,
+               &text[after..])
+        }
+    };
+    Ok(spliced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keeps_existing_entries() {
+        const LAUNCH: &str = r#"{
+    // comment with "quotes"
+    "version": "0.2.0",
+    /* block */
+    "configurations": [
+        { "name": "local", "type": "lldb", "program": "http://x//y", },
+        { "name": "remote", "type": "old" },
+    ]
+}"#;
+
+        let text = merge(Some(LAUNCH), json!({ "name": "remote", "type": "coreclr" })).unwrap();
+        assert!(text.contains("// comment with \"quotes\"") && text.contains("/* block */"));
+        let merged: Value = serde_json::from_str(&strip_jsonc(&text)).unwrap();
+        assert_eq!(merged["configurations"][0]["program"], "http://x//y");
+        assert_eq!(merged["configurations"][1]["type"], "coreclr");
+        assert_eq!(merged["configurations"].as_array().unwrap().len(), 2);
+        let created: Value = serde_json::from_str(&merge(None, json!({ "name": "a" })).unwrap()).unwrap();
+        assert_eq!(created["configurations"][0]["name"], "a");
+        for text in [r#"{ "version": "0.2.0" } // no configurations"#,
+                     r#"{ "configurations": [] }"#,
+                     "{}"]
+        {
+            let merged = merge(Some(text), json!({ "name": "a" })).unwrap();
+            let merged: Value = serde_json::from_str(&strip_jsonc(&merged)).unwrap();
+            assert_eq!(merged["configurations"], json!([{ "name": "a" }]), "{text}");
+        }
+        let appended = merge(Some(LAUNCH), json!({ "name": "new" })).unwrap();
+        let appended: Value = serde_json::from_str(&strip_jsonc(&appended)).unwrap();
+        assert_eq!(appended["configurations"][2]["name"], "new");
+    }
+}
@@ -0,0 +1,120 @@
+use crate::exit::{self, Exit};
+use crate::prelude::*;
+use crate::settings::{COMMON_TSH_ARGS, TSH_PROXY};
+use chrono::{DateTime, Duration, FixedOffset, Local};
+use itertools::Itertools;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoginStatus {
+    /// Until when, if tsh printed it in a known format
+    Valid(Option<DateTime<FixedOffset>>),
+    Expired,
+    Missing,
+}
+
+/// Reads the active profile from `tsh status` output, profiles for other proxies count as missing
+fn parse_status(status: &str, proxy: &str) -> LoginStatus {
+    let profile = status.lines()
+                        .skip_while(|x| !x.trim_start().starts_with("> Profile URL:"))
+                        .enumerate()
+                        .take_while(|(i, x)| *i == 0 || !x.contains("Profile URL:"))
+                        .map(snd)
+                        .collect_vec();
+    match profile.first() {
+        Some(url) if url.contains(proxy) => {}
+        _ => return LoginStatus::Missing,
+    }
+    match profile.iter().find_map(|x| x.trim_start().strip_prefix("Valid until:")) {
+        Some(valid) if !valid.contains("EXPIRED") => LoginStatus::Valid(parse_valid_until(valid)),
+        _ => LoginStatus::Expired,
+    }
+}
+
+/// `2022-11-04 02:44:57 +0100 CET [valid for 11h48m0s]`, the zone name and the rest ignored
+fn parse_valid_until(valid: &str) -> Option<DateTime<FixedOffset>> {
+    let until = valid.split_whitespace().take(3).join(" ");
+    DateTime::parse_from_str(&until, "%Y-%m-%d %H:%M:%S %z").ok()
+}
+
+pub fn status() -> Result<LoginStatus> {
+    let out = Command::new("tsh").args(["status", "--proxy", TSH_PROXY]).query()?;
+    if !out.status.success() {
+        return Ok(LoginStatus::Missing);
+    }
+    Ok(parse_status(&String::from_utf8_lossy(&out.stdout), TSH_PROXY))
+}
+
+/// Whether the expiry saved at `path` by a previous check is still a minute away
+fn known_valid(path: &Path) -> bool {
+    let until = std::fs::read_to_string(path).ok().and_then(|x| DateTime::parse_from_rfc3339(x.trim()).ok());
+    until.is_some_and(|x| x > Local::now() + Duration::minutes(1))
+}
+
+/// Runs `tsh login` when the certificate is missing or expired, `tsh status` skipped while the expiry saved at
+/// `path` is ahead
+pub fn ensure_login(path: &Path) -> Result<()> {
+    if known_valid(path) {
+        return Ok(());
+    }
+    match status()? {
+        LoginStatus::Valid(until) => {
+            if let Some(until) = until {
+                // only a cache, checking again next time if it can't be written
+                _ = std::fs::write(path, until.to_rfc3339());
+            }
+            return Ok(());
+        }
+        LoginStatus::Expired => eprintln!("Teleport certificate expired, logging in to {TSH_PROXY}..."),
+        LoginStatus::Missing => eprintln!("Not logged in to {TSH_PROXY}, logging in..."),
+    }
+    // stdout may be piped into another tool, e.g. `tt pick`
+    let status = Command::new("tsh").args(COMMON_TSH_ARGS).arg("login").stdout(std::io::stderr()).run()?;
+    if !status.success() {
+        return Err(Exit { code: exit::AUTH, message: "tsh login failed".into() }.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = r#"
+> Profile URL:        https://gate.mago.cloud:443
+  Logged in as:       paolo
+  Cluster:            gate.mago.cloud
+  Valid until:        2022-11-04 02:44:57 +0100 CET [valid for 11h48m0s]
+
+  Profile URL:        https://other.example.com:443
+  Logged in as:       paolo
+  Valid until:        2022-11-01 02:44:57 +0100 CET [EXPIRED]
+"#;
+
+    #[test]
+    fn parse_status_reads_active_profile() {
+        let until = DateTime::parse_from_rfc3339("2022-11-04T02:44:57+01:00").unwrap();
+        assert_eq!(parse_status(STATUS, "gate.mago.cloud"), LoginStatus::Valid(Some(until)));
+        assert_eq!(parse_status(&STATUS.replace("[valid for 11h48m0s]", "[EXPIRED]"), "gate.mago.cloud"),
+                   LoginStatus::Expired);
+        assert_eq!(parse_status(STATUS, "other.example.com"), LoginStatus::Missing);
+        assert_eq!(parse_status("Not logged in.", "gate.mago.cloud"), LoginStatus::Missing);
+        assert_eq!(parse_status(&STATUS.replace("2022-11-04 02:44:57 +0100", "tomorrow"),
+                                "gate.mago.cloud"),
+                   LoginStatus::Valid(None));
+    }
+
+    #[test]
+    fn known_valid_needs_a_future_expiry() {
+        let path = std::env::temp_dir().join(f!("tt-login-test-{}", std::process::id()));
+        assert!(!known_valid(&path));
+        std::fs::write(&path, (Local::now() + Duration::hours(1)).to_rfc3339()).unwrap();
+        assert!(known_valid(&path));
+        std::fs::write(&path, (Local::now() + Duration::seconds(30)).to_rfc3339()).unwrap();
+        assert!(!known_valid(&path));
+        std::fs::write(&path, "garbage").unwrap();
+        assert!(!known_valid(&path));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -4,25 +4,73 @@ use prelude::*;
 use settings::Settings;
 
 mod commands;
+mod config;
+mod exit;
 mod history;
+mod info;
+mod launch;
+mod login;
+mod picker;
+mod ping;
+mod platform;
 mod prelude;
+mod process;
 mod select;
 mod settings;
 mod ssh;
+mod ssh_config;
+mod state;
+mod sync;
+mod tail;
 mod teleport;
+mod transcript;
+mod tunnels;
+mod update;
 
 fn main() -> Result<()> {
+    match run() {
+        Err(err) => match err.downcast_ref::<exit::Exit>() {
+            Some(exit) => {
+                if !exit.message.is_empty() {
+                    eprintln!("Error: {}", exit.message);
+                }
+                std::process::exit(exit.code)
+            }
+            None => Err(err),
+        },
+        ok => ok,
+    }
+}
+
+fn run() -> Result<()> {
     let settings = Settings::new()?;
     match &settings.args.command {
         Some(cmd) => match cmd {
             Commands::Cp(args) => commands::cp(&settings, args),
+            Commands::Tunnel(args) => commands::tunnel(&settings, args),
+            Commands::Socks(args) => commands::socks(&settings, args),
+            Commands::Expose(args) => commands::expose(&settings, args),
+            Commands::Tunnels { action } => commands::tunnels(&settings, action),
             Commands::Exec { command } => commands::exec(&settings, command),
-            Commands::Code => commands::code(&settings),
+            Commands::Run { snippet } => commands::run(&settings, snippet),
+            Commands::Info(args) => commands::info(&settings, args),
+            Commands::Ping(args) => commands::ping(&settings, args),
+            Commands::Pick(args) => commands::pick(&settings, args),
+            Commands::Tail(args) => commands::tail(&settings, args),
+            Commands::Edit(args) => commands::edit(&settings, args),
+            Commands::Sync(args) => commands::sync(&settings, args),
+            Commands::Vsdbg(args) => commands::vsdbg(&settings, args),
+            Commands::DebugConfig(args) => commands::debug_config(&settings, args),
+            Commands::Code { path } => commands::code(&settings, path),
             Commands::Get { file } => commands::get_file(&settings, file),
             Commands::Put { file } => commands::put_file(&settings, file),
             Commands::Put { file } => commands::put_file(&settings, file),
             Commands::EventLog => todo!(),
-            Commands::Config => commands::append_tsh_to_ssh_config(),
+            Commands::Config { action } => commands::config(&settings, action),
+            Commands::Paths => commands::paths(&settings),
+            Commands::Logs { list } => commands::logs(&settings, *list),
+            Commands::Alias { action } => commands::alias(&settings, action),
+            Commands::Fav { action } => commands::fav(&settings, action),
             // Commands::Container { container } => todo!(),
             // Commands::Container { container } => match container {
             //     Container::EventLog => Container::win_container_event_log(hosts),
@@ -32,7 +80,7 @@ fn main() -> Result<()> {
             //     Container::Exec { command } => Container::exec(command, hosts),
             // },
         },
-        None => commands::ssh(&settings),
+        None => commands::launcher(&settings),
     }?;
     Ok(())
 }
@@ -0,0 +1,221 @@
+use crate::prelude::*;
+use dialoguer::console::{truncate_str, Key, Style, Term};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use itertools::Itertools;
+
+const MAX_VISIBLE: usize = 15;
+/// Preview height kept even for shorter previews, longer ones take rows from the list
+const PREVIEW_LINES: usize = 10;
+
+/// Fuzzy list drawn on stderr with a preview of the highlighted item underneath
+pub struct Picker<'a> {
+    items: &'a [String],
+    query: String,
+    preview: Box<dyn Fn(usize) -> Vec<String> + 'a>,
+    actions: &'a [(char, &'a str)],
+    /// What Enter does, shown with the actions
+    enter: &'a str,
+    /// Letters trigger actions instead of filtering, toggled with → and ←
+    action_mode: bool,
+    /// Tab marks items instead of moving down, see `interact_multi`
+    multi: bool,
+    marked: Vec<usize>,
+}
+
+impl<'a> Picker<'a> {
+    pub fn new(items: &'a [String], query: &str) -> Self {
+        Self { items,
+               query: query.to_owned(),
+               preview: Box::new(|_| vec![]),
+               actions: &[],
+               enter: "",
+               action_mode: false,
+               multi: false,
+               marked: vec![] }
+    }
+
+    /// Keys that pick the highlighted item with an action, once → switched the picker to action mode
+    pub fn with_actions(mut self, enter: &'a str, actions: &'a [(char, &'a str)]) -> Self {
+        self.enter = enter;
+        self.actions = actions;
+        self
+    }
+
+    /// Lines shown under the list for the item at this index
+    pub fn with_preview(mut self, preview: impl Fn(usize) -> Vec<String> + 'a) -> Self {
+        self.preview = Box::new(preview);
+        self
+    }
+
+    /// Matching item indices, best first, with the matched char positions
+    fn matches(&self) -> Vec<(usize, Vec<usize>)> {
+        let matcher = SkimMatcherV2::default().ignore_case();
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| matcher.fuzzy_indices(x, &self.query).map(|(score, idx)| (score, i, idx)))
+            .sorted_by_key(|(score, i, _)| (-score, *i))
+            .map(|(_, i, idx)| (i, idx))
+            .collect()
+    }
+
+    /// Index of the chosen item and the action key if one was used, `None` when cancelled with Esc or Ctrl-C
+    pub fn interact(mut self) -> Result<Option<(usize, Option<char>)>> {
+        self.interact_on_stderr()
+    }
+
+    /// Indices of the items marked with Tab, in marking order, or the highlighted one when none is marked
+    pub fn interact_multi(mut self) -> Result<Option<Vec<usize>>> {
+        self.multi = true;
+        let res = self.interact_on_stderr()?;
+        Ok(res.map(|(idx, _)| if self.marked.is_empty() { vec![idx] } else { std::mem::take(&mut self.marked) }))
+    }
+
+    fn interact_on_stderr(&mut self) -> Result<Option<(usize, Option<char>)>> {
+        let term = Term::stderr();
+        ensure!(term.is_term(), "host selection needs a terminal");
+        term.hide_cursor()?;
+        let res = self.run(&term);
+        term.show_cursor()?;
+        res
+    }
+
+    fn run(&mut self, term: &Term) -> Result<Option<(usize, Option<char>)>> {
+        let (mut cursor, mut offset, mut drawn) = (0, 0, 0);
+        loop {
+            let matches = self.matches();
+            cursor = cursor.min(matches.len().saturating_sub(1));
+            let preview = matches.get(cursor).map(|(i, _)| (self.preview)(*i)).unwrap_or_default();
+            // prompt, one list row, hint and rule
+            let rows = term.size().0 as usize;
+            let preview_height = preview.len().max(PREVIEW_LINES).min(rows.saturating_sub(4)).max(1);
+            let visible = MAX_VISIBLE.min(rows.saturating_sub(preview_height + 3)).max(1);
+            if cursor < offset {
+                offset = cursor;
+            } else if cursor >= offset + visible {
+                offset = cursor + 1 - visible;
+            }
+            term.clear_last_lines(drawn)?;
+            drawn = self.draw(term, &matches, cursor, offset, visible, (preview, preview_height))?;
+            let key = match term.read_key() {
+                Ok(key) => key,
+                // Ctrl-C, cancelling right away where Esc only leaves action mode
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
+                    term.clear_last_lines(drawn)?;
+                    return Ok(None);
+                }
+                Err(err) => return Err(err.into()),
+            };
+            match key {
+                Key::Enter if !matches.is_empty() => {
+                    term.clear_last_lines(drawn)?;
+                    return Ok(Some((matches[cursor].0, None)));
+                }
+                Key::ArrowRight if !self.actions.is_empty() => self.action_mode = true,
+                Key::ArrowLeft | Key::Escape if self.action_mode => self.action_mode = false,
+                Key::Char(c) if self.action_mode && !matches.is_empty() && self.actions.iter().any(|x| x.0 == c) => {
+                    term.clear_last_lines(drawn)?;
+                    return Ok(Some((matches[cursor].0, Some(c))));
+                }
+                Key::Char(_) if self.action_mode => {}
+                Key::Escape => {
+                    term.clear_last_lines(drawn)?;
+                    return Ok(None);
+                }
+                Key::Tab if self.multi && !matches.is_empty() => {
+                    let idx = matches[cursor].0;
+                    match self.marked.iter().position(|x| *x == idx) {
+                        Some(pos) => _ = self.marked.remove(pos),
+                        None => self.marked.push(idx),
+                    }
+                    cursor += 1;
+                }
+                Key::ArrowUp | Key::BackTab => cursor = cursor.saturating_sub(1),
+                Key::ArrowDown | Key::Tab => cursor += 1,
+                Key::PageUp => cursor = cursor.saturating_sub(visible),
+                Key::PageDown => cursor += visible,
+                Key::Home => cursor = 0,
+                Key::End => cursor = matches.len().saturating_sub(1),
+                Key::Backspace => {
+                    self.query.pop();
+                    cursor = 0;
+                }
+                Key::Char(c) if !c.is_control() => {
+                    self.query.push(c);
+                    cursor = 0;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Draws the prompt, list and preview padded to its height, returning the number of lines written
+    fn draw(&self,
+            term: &Term,
+            matches: &[(usize, Vec<usize>)],
+            cursor: usize,
+            offset: usize,
+            visible: usize,
+            (mut preview, height): (Vec<String>, usize))
+            -> Result<usize> {
+        let width = term.size().1 as usize;
+        let dim = Style::new().dim();
+        let green = Style::new().green();
+        let mut lines = vec![f!("{} {}", dim.apply_to("›"), self.query)];
+        for (pos, (i, idx)) in matches.iter().enumerate().skip(offset).take(visible) {
+            let item = truncate_str(&self.items[*i], width.saturating_sub(3 + self.multi as usize), "…");
+            let text = item.chars()
+                           .enumerate()
+                           .map(|(n, c)| if idx.contains(&n) { green.apply_to(c).to_string() } else { c.to_string() })
+                           .join("");
+            let mark = match self.multi {
+                true if self.marked.contains(i) => green.apply_to("+").to_string(),
+                true => " ".into(),
+                false => String::new(),
+            };
+            lines.push(if pos == cursor { f!("{}{mark} {text}", green.apply_to("❯")) } else { f!(" {mark} {text}") });
+        }
+        // keep the preview in place while the list shrinks
+        let list_height = 1 + visible.min(self.items.len());
+        lines.resize(list_height.max(lines.len()), String::new());
+        if self.action_mode {
+            let hints = self.actions.iter().map(|(key, name)| f!("{} {name}", green.apply_to(key))).join("  ");
+            lines.push(f!("{}  {hints}  {}",
+                          dim.apply_to(f!("enter {}", self.enter)),
+                          dim.apply_to("← back")));
+        } else if !self.actions.is_empty() {
+            lines.push(dim.apply_to("→ actions").to_string());
+        } else if self.multi {
+            lines.push(dim.apply_to(f!("tab mark  enter pick {}", self.marked.len().max(1))).to_string());
+        }
+        lines.push(dim.apply_to("─".repeat(width.min(80))).to_string());
+        if preview.len() > height {
+            // only when the terminal is too short for it
+            let hidden = preview.len() + 1 - height;
+            preview.truncate(height - 1);
+            preview.push(dim.apply_to(f!("… {hidden} more lines, enlarge the terminal to see them")).to_string());
+        }
+        preview.resize(height, String::new());
+        lines.extend(preview.iter().map(|x| truncate_str(x, width.saturating_sub(1), "…").into_owned()));
+        for line in &lines {
+            term.write_line(line)?;
+        }
+        Ok(lines.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_rank_best_first_then_by_position() {
+        let items = ["db-replica", "web-1", "db-primary", "web-2"].map(String::from);
+        let order = |query: &str| Picker::new(&items, query).matches().into_iter().map(|(i, _)| i).collect_vec();
+        assert_eq!(order(""), [0, 1, 2, 3]);
+        assert_eq!(order("web"), [1, 3]);
+        assert_eq!(order("DBP"), [2, 0]);
+        assert!(order("xyz").is_empty());
+    }
+}
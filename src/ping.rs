@@ -0,0 +1,105 @@
+use crate::prelude::*;
+use crate::settings::COMMON_TSH_ARGS;
+use crate::state;
+use crate::teleport::Host;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Last probe of a host
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Status {
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+    pub checked: String,
+}
+
+impl Status {
+    /// Selector column, fixed width
+    pub fn column(status: Option<&Status>) -> String {
+        match status {
+            Some(Status { latency_ms: Some(ms), .. }) => f!("{:>6}", f!("{ms}ms")),
+            Some(_) => f!("{:>6}", "down"),
+            None => f!("{:>6}", "?"),
+        }
+    }
+
+    pub fn row(&self, host: &str) -> Vec<String> {
+        match (self.latency_ms, &self.error) {
+            (Some(ms), _) => vec![host.to_owned(), "ok".into(), f!("{ms}ms")],
+            (None, error) => vec![host.to_owned(), "down".into(), error.clone().unwrap_or_default()],
+        }
+    }
+}
+
+pub const HEADER: &[&str] = &["HOST", "STATUS", "LATENCY/ERROR"];
+
+/// Runs a remote no-op, the latency covering the whole tsh round trip
+pub fn probe(host: &Host, timeout: Duration) -> Status {
+    let checked = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let (latency_ms, error) = match run(host, timeout) {
+        Ok(latency) => (Some(latency.as_millis() as u64), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    Status { latency_ms, error, checked }
+}
+
+fn run(host: &Host, timeout: Duration) -> Result<Duration> {
+    let start = Instant::now();
+    let mut child = Command::new("tsh").args(COMMON_TSH_ARGS)
+                                       .args(["ssh", &host.login(), "exit 0"])
+                                       .stdin(Stdio::null())
+                                       .stdout(Stdio::null())
+                                       .stderr(Stdio::piped())
+                                       .run_spawn()?;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            _ = child.kill();
+            _ = child.wait();
+            bail!("timed out after {}s", timeout.as_secs());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    let latency = start.elapsed();
+    if !status.success() {
+        let mut stderr = String::new();
+        child.stderr.take().context("can't take stderr")?.read_to_string(&mut stderr)?;
+        let reason = stderr.lines().map(str::trim).rfind(|x| !x.is_empty()).unwrap_or_default().to_owned();
+        bail!("{}",
+              if reason.is_empty() { f!("tsh exited with {status}") } else { reason });
+    }
+    Ok(latency)
+}
+
+/// Last known reachability by host key, shown in the selector
+#[derive(Serialize, Deserialize, Default)]
+pub struct StatusCache {
+    pub(crate) entries: BTreeMap<String, Status>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl StatusCache {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Ok(Self { path: path.to_owned(), ..state::load(path)? })
+    }
+
+    pub fn update(mut self, statuses: impl IntoIterator<Item = (String, Status)>) -> Result<Self> {
+        self.entries.extend(statuses);
+        self.save()?;
+        Ok(self)
+    }
+
+    pub(crate) fn save(&self) -> Result<()> {
+        state::save(&self.path, self)
+    }
+}
@@ -0,0 +1,188 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Label read by default to tell the OS of a node, e.g. `os: windows`
+pub const DEFAULT_LABEL: &str = "os";
+/// Default hostnames of Windows EC2 instances and fresh Windows installs
+const WINDOWS_HOSTNAME_PREFIXES: &[&str] = &["EC2AMAZ-", "WIN-"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    #[default]
+    Lnx,
+    Win,
+}
+
+impl Platform {
+    /// From the `label` label, then any label mentioning windows, then the hostname, Linux otherwise
+    pub fn detect(labels: &HashMap<String, String>, label: &str, hostname: &str) -> Self {
+        let windows = match labels.get(label) {
+            Some(value) => value.to_lowercase().starts_with("win"),
+            None =>
+                labels.values().any(|x| x.to_lowercase().contains("windows"))
+                || WINDOWS_HOSTNAME_PREFIXES.iter().any(|x| hostname.to_uppercase().starts_with(x)),
+        };
+        if windows {
+            Platform::Win
+        } else {
+            Platform::Lnx
+        }
+    }
+
+    pub fn login(&self) -> &'static str {
+        match self {
+            Platform::Lnx => "ubuntu",
+            Platform::Win => "Administrator",
+        }
+    }
+
+    /// Prefix elevating a command, Windows logins are already administrators
+    pub fn sudo(&self) -> &'static str {
+        match self {
+            Platform::Lnx => "sudo -n ",
+            Platform::Win => "",
+        }
+    }
+
+    /// Defines `d`, running docker with sudo when the login isn't in the docker group
+    pub fn docker(&self) -> &'static str {
+        match self {
+            Platform::Lnx =>
+                r#"d() { if docker ps >/dev/null 2>&1; then docker "$@"; else sudo -n docker "$@"; fi; }; "#,
+            Platform::Win => "function d { docker @args }; ",
+        }
+    }
+
+    /// Shell reading commands from stdin, used for browsing
+    pub fn shell(&self) -> &'static str {
+        match self {
+            Platform::Lnx => "sh",
+            Platform::Win => "powershell -NoProfile -NoLogo -NonInteractive -Command -",
+        }
+    }
+
+    /// Quotes `s` for the shell commands of this platform are written in (PowerShell on Windows)
+    pub fn quote(&self, s: &str) -> String {
+        match self {
+            Platform::Lnx => shell_quote(s),
+            Platform::Win => f!("'{}'", s.replace('\'', "''")),
+        }
+    }
+
+    /// Wraps a script written for this platform so the default ssh shell (cmd on Windows) runs it
+    pub fn script(&self, script: &str) -> String {
+        match self {
+            Platform::Lnx => script.to_owned(),
+            Platform::Win => f!("powershell -NoProfile -NonInteractive -Command \"{}\"",
+                                script.replace('"', "\\\"")),
+        }
+    }
+
+    pub fn pwd(&self) -> &'static str {
+        match self {
+            Platform::Lnx => "pwd",
+            Platform::Win => "(Get-Location).Path",
+        }
+    }
+
+    /// One entry per line, directories ending with '/', `./` and `../` included
+    pub fn list_dir(&self, dir: &str) -> String {
+        let dir = self.quote(dir);
+        match self {
+            Platform::Lnx => f!("ls --group-directories-first -pa1 {dir}"),
+            Platform::Win => {
+                f!("'./'; '../'; Get-ChildItem -Force -LiteralPath {dir} | Sort-Object {{ -not $_.PSIsContainer }}, Name \
+                    | ForEach-Object {{ if ($_.PSIsContainer) {{ $_.Name + '/' }} else {{ $_.Name }} }}")
+            }
+        }
+    }
+
+    pub fn separator(&self) -> char {
+        match self {
+            Platform::Lnx => '/',
+            Platform::Win => '\\',
+        }
+    }
+
+    pub fn home(&self) -> String {
+        match self {
+            Platform::Lnx => f!("/home/{}", self.login()),
+            Platform::Win => f!("C:\\Users\\{}", self.login()),
+        }
+    }
+
+    pub fn is_absolute(&self, path: &str) -> bool {
+        match self {
+            Platform::Lnx => path.starts_with('/'),
+            Platform::Win => path.get(1..3).is_some_and(|x| x == ":\\" || x == ":/") || path.starts_with("\\\\"),
+        }
+    }
+
+    pub fn join(&self, dir: &str, name: &str) -> String {
+        let sep = self.separator();
+        f!("{}{sep}{}",
+           dir.trim_end_matches(['/', '\\']),
+           name.trim_matches(['/', '\\']))
+    }
+
+    /// Parent folder, `dir` itself at the root
+    pub fn parent(&self, dir: &str) -> String {
+        let trimmed = dir.trim_end_matches(['/', '\\']);
+        match trimmed.rsplit_once(['/', '\\']) {
+            Some(("", _)) => "/".into(),
+            Some((parent, _)) if self == &Platform::Win && parent.len() == 2 => f!("{parent}\\"),
+            Some((parent, _)) => parent.into(),
+            None => dir.into(),
+        }
+    }
+
+    /// Path as it appears in a vscode-remote uri, e.g. `/c:/Users/x` on Windows
+    pub fn uri_path(&self, path: &str) -> String {
+        match self {
+            Platform::Lnx => path.to_owned(),
+            Platform::Win => f!("/{}", path.replace('\\', "/")),
+        }
+    }
+
+    /// Home relative `path` made absolute
+    pub fn absolute(&self, path: &str) -> String {
+        if self.is_absolute(path) {
+            path.to_owned()
+        } else {
+            self.join(&self.home(),
+                      path.trim_start_matches("~").trim_start_matches(['/', '\\']))
+        }
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+                        Platform::Lnx => "Linux",
+                        Platform::Win => "Windows",
+                    })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_and_paths() {
+        let labels = HashMap::from([("os".to_owned(), "Windows".to_owned())]);
+        assert_eq!(Platform::detect(&labels, "os", "web-1"), Platform::Win);
+        assert_eq!(Platform::detect(&labels, "platform", "web-1"), Platform::Win);
+        assert_eq!(Platform::detect(&HashMap::new(), "os", "EC2AMAZ-12AB"), Platform::Win);
+        assert_eq!(Platform::detect(&HashMap::new(), "os", "web-1"), Platform::Lnx);
+        let win = Platform::Win;
+        assert_eq!(win.join("C:\\Users\\", "app/"), "C:\\Users\\app");
+        assert_eq!(win.parent("C:\\Users"), "C:\\");
+        assert_eq!(win.absolute("~/src"), "C:\\Users\\Administrator\\src");
+        assert_eq!(win.uri_path("C:\\src"), "/C:/src");
+        assert_eq!(Platform::Lnx.parent("/home"), "/");
+        assert_eq!(Platform::Lnx.absolute("~/src"), "/home/ubuntu/src");
+    }
+}
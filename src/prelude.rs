@@ -1,9 +1,9 @@
+pub use crate::process::Run;
 pub use eyre::{bail, ensure, eyre, Context, ContextCompat, Result, WrapErr};
 pub use std::format as f;
 pub use std::println as p;
 use std::time::Instant;
 
-#[allow(dead_code)]
 pub fn stopwatch_guard(name: &str) -> StopwatchGuard {
     let start = Instant::now();
     StopwatchGuard { name: name.to_string(), start }
@@ -14,9 +14,16 @@ pub struct StopwatchGuard {
     start: Instant,
 }
 
+impl StopwatchGuard {
+    /// Names what was timed once it's known, e.g. with its outcome
+    pub fn rename(&mut self, name: String) {
+        self.name = name;
+    }
+}
+
 impl Drop for StopwatchGuard {
     fn drop(&mut self) {
-        p!("{} took {}ms", self.name, self.start.elapsed().as_millis())
+        eprintln!("{} took {}ms", self.name, self.start.elapsed().as_millis())
     }
 }
 
@@ -64,3 +71,27 @@ impl<T, E> Inspect<T, E> for Result<T, E> {
         }
     }
 }
+
+/// Single quotes `s` for a POSIX shell
+pub fn shell_quote(s: &str) -> String {
+    f!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Renders rows as left aligned columns, rows may be shorter than the header
+pub fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let header = header.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    let mut widths = vec![0; header.len()];
+    for row in rows.iter().chain([&header]) {
+        for (i, cell) in row.iter().enumerate().take(widths.len()) {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    [&header].into_iter()
+             .chain(rows)
+             .map(|row| {
+                 let line = row.iter().enumerate().map(|(i, x)| f!("{x:w$}", w = widths[i])).collect::<Vec<_>>();
+                 line.join("  ").trim_end().to_owned()
+             })
+             .collect::<Vec<_>>()
+             .join("\n")
+}
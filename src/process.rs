@@ -0,0 +1,169 @@
+use crate::prelude::*;
+use itertools::Itertools;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// `-v` logs every external command with its duration and exit status, `--dry-run` prints them instead
+pub fn configure(verbose: bool, dry_run: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Command line as it could be pasted into a shell
+pub fn display(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program()).chain(cmd.get_args())
+                                      .map(|x| x.to_string_lossy())
+                                      .map(|x| {
+                                          let plain = !x.is_empty()
+                                                      && x.chars()
+                                                          .all(|c| c.is_alphanumeric() || "-_./:=@,+%~".contains(c));
+                                          if plain {
+                                              x.into_owned()
+                                          } else {
+                                              shell_quote(&x)
+                                          }
+                                      })
+                                      .join(" ")
+}
+
+fn describe(status: &ExitStatus) -> String {
+    status.code().map(|x| f!("exit {x}")).unwrap_or_else(|| "killed".into())
+}
+
+/// Runs `run` under a stopwatch when verbose, naming it after the command and outcome
+fn logged<T>(cmd: &mut Command,
+             run: impl FnOnce(&mut Command) -> io::Result<T>,
+             outcome: impl Fn(&T) -> String)
+             -> io::Result<T> {
+    if !VERBOSE.load(Ordering::Relaxed) {
+        return run(cmd);
+    }
+    let line = display(cmd);
+    let mut stopwatch = stopwatch_guard(&f!("+ {line}"));
+    let res = run(cmd);
+    stopwatch.rename(f!("+ {line} [{}]",
+                        res.as_ref().map(outcome).unwrap_or_else(|err| err.to_string())));
+    res
+}
+
+/// `status`, `output` and `spawn` honouring `-v` and `--dry-run`
+pub trait Run {
+    fn run(&mut self) -> io::Result<ExitStatus>;
+    fn run_output(&mut self) -> io::Result<Output>;
+    /// Read only command tt needs the output of to go on, e.g. `tsh ls`, run even with `--dry-run`
+    fn query(&mut self) -> io::Result<Output>;
+    fn run_spawn(&mut self) -> io::Result<Process>;
+}
+
+impl Run for Command {
+    fn run(&mut self) -> io::Result<ExitStatus> {
+        if is_dry_run() {
+            p!("{}", display(self));
+            return Ok(ExitStatus::default());
+        }
+        logged(self, |x| x.status(), describe)
+    }
+
+    fn run_output(&mut self) -> io::Result<Output> {
+        if is_dry_run() {
+            p!("{}", display(self));
+            return Ok(Output { status: ExitStatus::default(), stdout: vec![], stderr: vec![] });
+        }
+        logged(self, |x| x.output(), |x| describe(&x.status))
+    }
+
+    fn query(&mut self) -> io::Result<Output> {
+        logged(self, |x| x.output(), |x| describe(&x.status))
+    }
+
+    /// With `--dry-run` a no-op stands in for the command, its pipes ending right away
+    fn run_spawn(&mut self) -> io::Result<Process> {
+        let mut noop;
+        let cmd = if is_dry_run() {
+            p!("{}", display(self));
+            noop = if cfg!(windows) { Command::new("cmd") } else { Command::new("true") };
+            noop.args(if cfg!(windows) { &["/C", "rem"][..] } else { &[] })
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            &mut noop
+        } else {
+            self
+        };
+        let stopwatch = VERBOSE.load(Ordering::Relaxed).then(|| (display(cmd), stopwatch_guard("")));
+        Ok(Process { child: cmd.spawn()?, stopwatch })
+    }
+}
+
+/// Child logging its duration and exit status once waited for, when verbose
+pub struct Process {
+    child: Child,
+    stopwatch: Option<(String, StopwatchGuard)>,
+}
+
+impl Process {
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        let status = self.child.wait();
+        self.log(&status);
+        status
+    }
+
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        let status = self.child.try_wait();
+        if let Ok(Some(x)) = &status {
+            self.log(&Ok(*x));
+        }
+        status
+    }
+
+    /// Logs the outcome, the stopwatch printing it when dropped
+    fn log(&mut self, status: &io::Result<ExitStatus>) {
+        if let Some((line, mut stopwatch)) = self.stopwatch.take() {
+            stopwatch.rename(f!("+ {line} [{}]",
+                                status.as_ref().map(describe).unwrap_or_else(|err| err.to_string())));
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if let Some((line, mut stopwatch)) = self.stopwatch.take() {
+            stopwatch.rename(f!("+ {line} [left running, pid {}]", self.child.id()));
+        }
+    }
+}
+
+impl Deref for Process {
+    type Target = Child;
+
+    fn deref(&self) -> &Child {
+        &self.child
+    }
+}
+
+impl DerefMut for Process {
+    fn deref_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_quotes_only_when_needed() {
+        let mut cmd = Command::new("tsh");
+        cmd.args(["ssh", "ubuntu@web-1", "ls -la '/tmp'"]);
+        assert_eq!(display(&cmd), r"tsh ssh ubuntu@web-1 'ls -la '\''/tmp'\'''");
+    }
+}
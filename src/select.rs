@@ -1,3 +1,5 @@
+use crate::picker::Picker;
+use crate::ping::Status;
 use crate::prelude::*;
 use crate::teleport::{Host, Hosts};
 use dialoguer::console::{Color, Style};
@@ -6,6 +8,7 @@ use dialoguer::FuzzySelect;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap};
 use std::process::exit;
 
 pub fn select_str(message: &str, options: &Vec<String>, start_value: &str) -> Result<String> {
@@ -42,19 +45,149 @@ pub fn select(message: &str, options: &Vec<String>, start_value: &str) -> Result
         .default(0)
         .items(options)
         .interact_opt()?
-        .unwrap_or_else(|| exit(0));
+        .unwrap_or_else(|| exit(1));
     Ok(selection)
 }
 
 pub struct SelectArgs {
     pub hosts: Hosts,
     pub start_value: String,
+    pub favorites: Vec<String>,
+    /// Last known reachability by host key, shown as a column when set
+    pub statuses: Option<BTreeMap<String, Status>>,
+    /// What tt knows about each host by key, shown in the preview
+    pub usage: HashMap<String, HostUsage>,
+    /// Action keys offered besides Enter, see `Picker::with_actions`
+    pub actions: &'static [(char, &'static str)],
 }
 
-pub fn select_teleport_host(SelectArgs { hosts, start_value }: &SelectArgs) -> Result<Host> {
+/// Local state about a host, besides what `tsh ls` reports
+#[derive(Default)]
+pub struct HostUsage {
+    /// Position in the recent hosts, 0 being the last used
+    pub recent: Option<usize>,
+    pub folders: Vec<String>,
+    pub tunnels: Vec<String>,
+    pub status: Option<Status>,
+}
+
+pub const FAVORITE_GLYPH: &str = "★";
+const LABELS_WIDTH: usize = 100;
+
+fn preview(host: &Host, usage: Option<&HostUsage>) -> Vec<String> {
+    let dim = Style::new().dim();
+    let field = |name: &str, value: String| f!("{} {value}", dim.apply_to(f!("{name:>8}")));
+    let spec = &host.spec;
+    let mut lines = vec![field("host", f!("{} [{}] {}", spec.hostname, host.key(), host.platform())),
+                         field("addr",
+                               f!("{}  public: {}", spec.addr, spec.public_addr.as_deref().unwrap_or("-"))),
+                         field("agent",
+                               f!("{}  reverse tunnel: {}  expires: {}",
+                                  spec.version,
+                                  if spec.use_tunnel.unwrap_or_default() { "yes" } else { "no" },
+                                  if host.metadata.expires.is_empty() { "-" } else { &host.metadata.expires }))];
+    if let Some(usage) = usage {
+        let recent = usage.recent.map(|x| f!("#{}", x + 1)).unwrap_or_else(|| "-".into());
+        let ping = match &usage.status {
+            Some(x) => f!("{} at {}", Status::column(Some(x)).trim(), x.checked),
+            None => "-".into(),
+        };
+        let folders = if usage.folders.is_empty() { "-".into() } else { usage.folders.join(", ") };
+        lines.push(field("history", f!("recent: {recent}  ping: {ping}  folders: {folders}")));
+        if !usage.tunnels.is_empty() {
+            lines.push(field("tunnels", usage.tunnels.join(", ")));
+        }
+    }
+    let mut chunks: Vec<String> = vec![];
+    for label in host.metadata.labels.iter().sorted().map(|(k, v)| f!("{k}={v}")) {
+        match chunks.last_mut() {
+            Some(chunk) if chunk.len() + label.len() < LABELS_WIDTH => *chunk = f!("{chunk}  {label}"),
+            _ => chunks.push(label),
+        }
+    }
+    lines.extend(chunks.into_iter().enumerate().map(|(i, x)| field(if i == 0 { "labels" } else { "" }, x)));
+    lines
+}
+
+/// Selector lines, and the only host matching `start_value` if there is exactly one
+fn items(args: &SelectArgs) -> Result<(Vec<String>, Option<usize>)> {
+    let SelectArgs { hosts, start_value, favorites, statuses, .. } = args;
     let width = hosts.iter().map(|x| x.spec.hostname.len()).max().unwrap_or(20);
-    let values = hosts.iter().map(|h| f!("{:width$} [{h}]", h.spec.hostname.clone())).collect_vec();
-    let idx = select("", &values, start_value)?;
-    let selected = hosts.get(idx).unwrap();
-    Ok(selected.clone())
+    let values = hosts.iter()
+                      .map(|h| {
+                          let pin = if favorites.iter().any(|k| k == h.key()) { FAVORITE_GLYPH } else { " " };
+                          let status = match statuses {
+                              Some(statuses) => f!("{} ", Status::column(statuses.get(h.key()))),
+                              None => String::new(),
+                          };
+                          f!("{pin} {status}{:width$} [{h}]", h.spec.hostname.clone())
+                      })
+                      .collect_vec();
+    if hosts.is_empty() {
+        bail!("Host list is empty");
+    }
+    if !start_value.is_empty() {
+        let matcher = SkimMatcherV2::default().ignore_case();
+        let filtered = values.iter().positions(|x| matcher.fuzzy_match(x, start_value).is_some()).collect_vec();
+        match filtered[..] {
+            [] => bail!("No host found"),
+            [idx] => return Ok((values, Some(idx))),
+            _ => {}
+        }
+    }
+    Ok((values, None))
+}
+
+/// The chosen host and the action key used, if any
+pub fn select_teleport_host(args: &SelectArgs) -> Result<(Host, Option<char>)> {
+    let SelectArgs { hosts, start_value, usage, actions, .. } = args;
+    let (values, only) = items(args)?;
+    if let Some(idx) = only {
+        return Ok((hosts[idx].clone(), None));
+    }
+    let preview = |i: usize| preview(&hosts[i], usage.get(hosts[i].key()));
+    let (idx, action) = Picker::new(&values, start_value).with_preview(preview)
+                                                         .with_actions("ssh", actions)
+                                                         .interact()?
+                                                         .unwrap_or_else(|| exit(1));
+    Ok((hosts[idx].clone(), action))
+}
+
+/// Hosts marked with Tab when `multi`, or the highlighted one, empty when cancelled
+pub fn select_teleport_hosts(args: &SelectArgs, multi: bool) -> Result<Vec<Host>> {
+    let SelectArgs { hosts, start_value, usage, .. } = args;
+    let (values, only) = items(args)?;
+    if let Some(idx) = only {
+        return Ok(vec![hosts[idx].clone()]);
+    }
+    let preview = |i: usize| preview(&hosts[i], usage.get(hosts[i].key()));
+    let picker = Picker::new(&values, start_value).with_preview(preview);
+    let picked = if multi { picker.interact_multi()? } else { picker.interact()?.map(|(idx, _)| vec![idx]) };
+    Ok(picked.unwrap_or_default().into_iter().map(|i| hosts[i].clone()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(start_value: &str) -> SelectArgs {
+        SelectArgs { hosts: vec![Host::test("k1", "db-primary", &[("env", "prod")]),
+                                 Host::test("k2", "web-1", &[])],
+                     start_value: start_value.into(),
+                     favorites: vec!["k2".into()],
+                     statuses: None,
+                     usage: HashMap::new(),
+                     actions: &[] }
+    }
+
+    #[test]
+    fn items_skip_the_selector_for_a_single_match() {
+        let (values, only) = items(&args("")).unwrap();
+        assert_eq!(values,
+                   ["  db-primary [env: prod]", &f!("{FAVORITE_GLYPH} web-1      []")]);
+        assert_eq!(only, None);
+        assert_eq!(items(&args("prod")).unwrap().1, Some(0));
+        assert_eq!(items(&args("-")).unwrap().1, None);
+        assert!(items(&args("nothing")).is_err());
+    }
 }
@@ -1,26 +1,21 @@
-use crate::{commands::Commands, prelude::*};
+use crate::{commands::Commands, config::Config, exit, prelude::*, process, update};
 use clap::Parser;
 use clap_complete::Shell;
 use const_format::concatcp;
 use directories::UserDirs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const NAME: &str = env!("CARGO_PKG_NAME");
 pub const CONFIG_FILE_NAME: &str = concatcp!(NAME, ".config.json");
-pub const COMMON_TSH_ARGS: &[&str] = &["--proxy", "gate.mago.cloud", "--auth", "github"];
+pub const TSH_PROXY: &str = "gate.mago.cloud";
+pub const COMMON_TSH_ARGS: &[&str] = &["--proxy", TSH_PROXY, "--auth", "github"];
 pub const VSDBGSH: &str = include_str!("../res/vsdbg.sh");
 pub const VSDBGSH_FILE_NAME: &str = "vsdbg.sh";
-
-fn check_update() -> Result<()> {
-    if !cfg!(windows) {
-        bail!("not implemented on this platform");
-    }
-    std::process::Command::new("scoop.cmd").args(["update", "-k", NAME]).spawn()?;
-    Ok(())
-}
+/// Keeps config, cache and state together in this directory instead of the XDG ones
+pub const CONFIG_DIR_VAR: &str = "TT_CONFIG_DIR";
 
 #[derive(Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, after_help = exit::HELP)]
 pub struct AshArgs {
     #[arg(name("[profile:]host"), help("Remote Host"))]
     pub host: Option<String>,
@@ -30,9 +25,18 @@ pub struct AshArgs {
     /// Update cache
     #[arg(short, long, default_value_t = false)]
     pub update: bool,
+    /// Record a transcript of ssh/exec sessions
+    #[arg(long, default_value_t = false)]
+    pub record: bool,
+    /// Log every external command with its duration and exit status to stderr
+    #[arg(short, long, global = true, default_value_t = false)]
+    pub verbose: bool,
+    /// Print the external commands instead of running them, read only tsh queries still run
+    #[arg(long, global = true, default_value_t = false)]
+    pub dry_run: bool,
     #[command(subcommand)]
     pub command: Option<Commands>,
-    /// Check for ash update
+    /// Check for tt update and install it
     #[arg(long, default_value_t = false)]
     pub check_update: bool,
 
@@ -47,40 +51,97 @@ pub struct Settings {
     pub config_dir: PathBuf,
     pub config_path: PathBuf,
     pub history_path: PathBuf,
+    pub folder_history_path: PathBuf,
     pub cache_path: PathBuf,
+    pub transcripts_dir: PathBuf,
+    pub tunnels_path: PathBuf,
+    pub status_path: PathBuf,
+    pub login_path: PathBuf,
     pub code_cmd: String,
     pub vsdbgsh_path: PathBuf,
     pub args: AshArgs,
     pub start_value: String,
+    pub config: Config,
 }
 
 impl Settings {
     pub fn new() -> Result<Self> {
         let user_dirs = UserDirs::new().expect("can't get user dirs");
         let home_dir = user_dirs.home_dir().to_owned();
-        let config_dir = user_dirs.home_dir().join(".config").join(NAME);
+        let override_dir = std::env::var_os(CONFIG_DIR_VAR).map(PathBuf::from);
+        let (config_dir, cache_dir, state_dir) = match &override_dir {
+            Some(dir) => (dir.clone(), dir.clone(), dir.clone()),
+            None => (xdg_dir(&home_dir, "XDG_CONFIG_HOME", ".config"),
+                     xdg_dir(&home_dir, "XDG_CACHE_HOME", ".cache"),
+                     xdg_dir(&home_dir, "XDG_STATE_HOME", ".local/state")),
+        };
         let config_path = config_dir.join(CONFIG_FILE_NAME);
-        let history_path = config_dir.join("history");
-        let cache_path = config_dir.join("cache");
-        let code_cmd = if cfg!(windows) { "code.cmd" } else { "code" }.into();
         let vsdbgsh_path = config_dir.join(VSDBGSH_FILE_NAME);
+        let cache_path = cache_dir.join("cache");
+        let status_path = cache_dir.join("status");
+        let login_path = cache_dir.join("login");
+        let history_path = state_dir.join("history");
+        let folder_history_path = state_dir.join("folder_history");
+        let transcripts_dir = state_dir.join("transcripts");
+        let tunnels_path = state_dir.join("tunnels");
+        let code_cmd = if cfg!(windows) { "code.cmd" } else { "code" }.into();
         let args = AshArgs::parse();
+        process::configure(args.verbose, args.dry_run);
+        if override_dir.is_none() {
+            let legacy_dir = home_dir.join(".config").join(NAME);
+            migrate(&legacy_dir,
+                    &[&config_path,
+                      &cache_path,
+                      &status_path,
+                      &history_path,
+                      &folder_history_path,
+                      &transcripts_dir,
+                      &tunnels_path])?;
+        }
         let start_value = args.host.clone().unwrap_or_default();
+        let config = match Config::load(&config_path) {
+            Ok(config) => config,
+            // 'tt config' reports or fixes a broken config itself
+            Err(_) if matches!(args.command, Some(Commands::Config { .. })) => Config::default(),
+            Err(err) => return Err(err),
+        };
         if args.check_update {
-            check_update()?;
+            update::check_update(&config)?;
             std::process::exit(0)
         }
         if args.reset {
-            if config_dir.exists() {
-                std::fs::remove_dir_all(&config_dir)?;
+            // only what tt wrote, $TT_CONFIG_DIR may be a directory shared with other tools
+            let backup = config_path.with_extension("json.bak");
+            let tunnels_lock = tunnels_path.with_extension("lock");
+            let files = [&config_path,
+                         &backup,
+                         &vsdbgsh_path,
+                         &cache_path,
+                         &status_path,
+                         &login_path,
+                         &history_path,
+                         &folder_history_path,
+                         &tunnels_path,
+                         &tunnels_lock];
+            for file in files.into_iter().filter(|x| x.exists()) {
+                std::fs::remove_file(file)?;
+            }
+            if transcripts_dir.exists() {
+                std::fs::remove_dir_all(&transcripts_dir)?;
+            }
+            for dir in [&config_dir, &cache_dir, &state_dir] {
+                // fails unless empty
+                _ = std::fs::remove_dir(dir);
             }
             std::process::exit(0)
         }
         if args.update && cache_path.exists() {
             std::fs::remove_file(&cache_path)?;
         }
-        std::fs::create_dir_all(&config_dir)?;
-        if !vsdbgsh_path.exists() {
+        for dir in [&config_dir, &cache_dir, &state_dir] {
+            std::fs::create_dir_all(dir)?;
+        }
+        if std::fs::read_to_string(&vsdbgsh_path).ok().as_deref() != Some(VSDBGSH) {
             std::fs::write(&vsdbgsh_path, VSDBGSH)?;
         }
         Ok(Self {
@@ -89,11 +150,107 @@ impl Settings {
             config_dir,
             config_path,
             history_path,
+            folder_history_path,
             cache_path,
+            transcripts_dir,
+            tunnels_path,
+            status_path,
+            login_path,
             code_cmd,
             vsdbgsh_path,
             args,
             start_value,
+            config,
         })
     }
 }
+
+/// `$var/tt` when `var` is an absolute path, as the XDG spec requires, `~/default/tt` otherwise
+fn xdg_dir(home: &Path, var: &str, default: &str) -> PathBuf {
+    match std::env::var_os(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir.join(NAME),
+        _ => home.join(default).join(NAME),
+    }
+}
+
+/// Moves files left in `legacy_dir`, where everything lived before XDG paths, to `paths` unless already there
+fn migrate(legacy_dir: &Path, paths: &[&PathBuf]) -> Result<()> {
+    for path in paths {
+        let old = legacy_dir.join(path.file_name().context("path without file name")?);
+        if old != **path && old.exists() && !path.exists() {
+            std::fs::create_dir_all(path.parent().context("path without parent")?)?;
+            move_path(&old, path).wrap_err_with(|| f!("can't move {} to {}", old.display(), path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Renames, copying when `to` is on another file system
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            move_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        std::fs::remove_dir(from)?;
+    } else {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn xdg_dir_ignores_relative_paths() {
+        let home = Path::new("/home/me");
+        std::env::set_var("TT_TEST_XDG_ABSOLUTE", "/xdg/config");
+        std::env::set_var("TT_TEST_XDG_RELATIVE", "relative/config");
+        assert_eq!(xdg_dir(home, "TT_TEST_XDG_ABSOLUTE", ".config"),
+                   Path::new("/xdg/config/tt"));
+        assert_eq!(xdg_dir(home, "TT_TEST_XDG_RELATIVE", ".config"),
+                   home.join(".config/tt"));
+        assert_eq!(xdg_dir(home, "TT_TEST_XDG_UNSET", ".local/state"),
+                   home.join(".local/state/tt"));
+    }
+
+    #[test]
+    fn migrate_moves_files_and_directories_once() {
+        let root = std::env::temp_dir().join(f!("tt-migrate-test-{}", std::process::id()));
+        let (legacy, state) = (root.join("legacy"), root.join("state"));
+        std::fs::create_dir_all(legacy.join("transcripts")).unwrap();
+        std::fs::create_dir_all(&state).unwrap();
+        for (path, text) in [(legacy.join(CONFIG_FILE_NAME), "config"),
+                             (legacy.join("history"), "history"),
+                             (legacy.join("transcripts/a.log"), "log"),
+                             (legacy.join("tunnels"), "old tunnels"),
+                             (state.join("tunnels"), "new tunnels")]
+        {
+            std::fs::write(path, text).unwrap();
+        }
+        // the config stays where it is, the state moves unless it's already there
+        let paths = [legacy.join(CONFIG_FILE_NAME),
+                     state.join("history"),
+                     state.join("transcripts"),
+                     state.join("tunnels")];
+        let paths = paths.iter().collect::<Vec<_>>();
+        migrate(&legacy, &paths).unwrap();
+        migrate(&legacy, &paths).unwrap();
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(legacy.join(CONFIG_FILE_NAME)), "config");
+        assert_eq!(read(state.join("history")), "history");
+        assert_eq!(read(state.join("transcripts/a.log")), "log");
+        assert_eq!(read(state.join("tunnels")), "new tunnels");
+        assert_eq!(read(legacy.join("tunnels")), "old tunnels");
+        assert!(!legacy.join("history").exists() && !legacy.join("transcripts").exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,3 +1,4 @@
+use crate::process::Run;
 use eyre::*;
 use std::{
     io::{BufReader, Read, Write},
@@ -11,9 +12,12 @@ pub struct Ssh {
 }
 
 impl Ssh {
-    pub fn new(host_name: &str) -> Result<Self> {
-        let mut child =
-            Command::new("ssh").args(["-T", host_name, "sh"]).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    /// `shell` reads commands from stdin, e.g. `sh`
+    pub fn new(host_name: &str, shell: &str) -> Result<Self> {
+        let mut child = Command::new("ssh").args(["-T", host_name, shell])
+                                           .stdin(Stdio::piped())
+                                           .stdout(Stdio::piped())
+                                           .run_spawn()?;
         let stdin = child.stdin.take().ok_or_else(|| eyre!("can't take stdin"))?;
         let stdout = child.stdout.take().ok_or_else(|| eyre!("can't take stdout"))?;
         let stdout = BufReader::new(stdout);
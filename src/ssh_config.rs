@@ -0,0 +1,151 @@
+use crate::login;
+use crate::prelude::*;
+use crate::settings::COMMON_TSH_ARGS;
+use similar::TextDiff;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const BEGIN: &str = "# Begin generated Teleport configuration";
+const END: &str = "# End generated Teleport configuration";
+
+fn path() -> Result<PathBuf> {
+    let home = directories::UserDirs::new().context("can't retrieve home directory")?.home_dir().to_owned();
+    Ok(home.join(".ssh").join("config"))
+}
+
+fn read(path: &Path) -> Result<String> {
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    std::fs::read_to_string(path).wrap_err_with(|| f!("can't read {}", path.display()))
+}
+
+fn generate(login_path: &Path) -> Result<String> {
+    login::ensure_login(login_path)?;
+    let out = Command::new("tsh").args(COMMON_TSH_ARGS).args(["config"]).query()?;
+    if !out.status.success() {
+        bail!("tsh config failed: {}", String::from_utf8_lossy(&out.stderr).trim());
+    }
+    let block = String::from_utf8(out.stdout)?;
+    ensure!(block.contains(BEGIN) && block.contains(END),
+            "unexpected tsh config output:\n{block}");
+    Ok(block)
+}
+
+/// Byte range of the generated block, from the Begin marker to the end of the End marker line
+fn find_block(content: &str) -> Option<(usize, usize)> {
+    let start = content.find(BEGIN)?;
+    let end = start + content[start..].find(END)?;
+    let end = content[end..].find('\n').map(|x| end + x + 1).unwrap_or(content.len());
+    Some((start, end))
+}
+
+/// Replaces the generated block with `block`, appending it when missing or removing it when `None`
+fn replace_block(content: &str, block: Option<&str>) -> String {
+    let block = block.map(|x| if x.ends_with('\n') { x.to_owned() } else { f!("{x}\n") }).unwrap_or_default();
+    match find_block(content) {
+        Some((start, end)) => f!("{}{block}{}", &content[..start], &content[end..]),
+        None if content.is_empty() || content.ends_with('\n') => f!("{content}{block}"),
+        None => f!("{content}\n{block}"),
+    }
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    if path.exists() {
+        let backup = path.with_file_name(f!("config.{}.bak", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+        std::fs::copy(path, &backup)?;
+        p!("Backup saved to {}", backup.display());
+    } else if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+        }
+    }
+    std::fs::write(path, content)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// Adds the Teleport block when missing, silently
+pub fn ensure_installed(login_path: &Path) -> Result<()> {
+    let path = path()?;
+    let content = read(&path)?;
+    if find_block(&content).is_none() {
+        write(&path, &replace_block(&content, Some(&generate(login_path)?)))?;
+    }
+    Ok(())
+}
+
+pub fn install(login_path: &Path) -> Result<()> {
+    let path = path()?;
+    if find_block(&read(&path)?).is_some() {
+        p!("Teleport configuration already in {}, use 'update' to refresh it",
+           path.display());
+        return Ok(());
+    }
+    ensure_installed(login_path)?;
+    p!("Teleport configuration added to {}", path.display());
+    Ok(())
+}
+
+pub fn update(login_path: &Path) -> Result<()> {
+    let path = path()?;
+    let content = read(&path)?;
+    let updated = replace_block(&content, Some(&generate(login_path)?));
+    if updated == content {
+        p!("Teleport configuration in {} is up to date", path.display());
+        return Ok(());
+    }
+    write(&path, &updated)?;
+    p!("Teleport configuration updated in {}", path.display());
+    Ok(())
+}
+
+pub fn diff(login_path: &Path) -> Result<()> {
+    let path = path()?;
+    let content = read(&path)?;
+    let updated = replace_block(&content, Some(&generate(login_path)?));
+    let name = path.to_string_lossy();
+    print!("{}",
+           TextDiff::from_lines(&content, &updated).unified_diff().header(&name, &name));
+    Ok(())
+}
+
+pub fn remove() -> Result<()> {
+    let path = path()?;
+    let content = read(&path)?;
+    if find_block(&content).is_none() {
+        p!("No Teleport configuration in {}", path.display());
+        return Ok(());
+    }
+    write(&path, &replace_block(&content, None))?;
+    p!("Teleport configuration removed from {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: &str = r#"# Begin generated Teleport configuration for gate
+Host *.aws
+# End generated Teleport configuration
+"#;
+
+    #[test]
+    fn replace_block_appends_replaces_and_removes() {
+        let appended = replace_block("Host a\n  User b", Some(BLOCK));
+        assert_eq!(appended, f!("Host a\n  User b\n{BLOCK}"));
+        let content = f!("Host a\n{BLOCK}Host c\n");
+        let replaced = replace_block(&content, Some(&BLOCK.replace("*.aws", "*.gcp")));
+        assert_eq!(replaced, f!("Host a\n{}Host c\n", BLOCK.replace("*.aws", "*.gcp")));
+        assert_eq!(replace_block(&content, None), "Host a\nHost c\n");
+        assert_eq!(replace_block("", Some(BLOCK)), BLOCK);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::prelude::*;
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Reads the JSON state tt keeps at `path`, e.g. history or tunnels, `T::default()` until it's first saved
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    match std::fs::read(path) {
+        Ok(json) =>
+            serde_json::from_slice(&json).wrap_err_with(|| f!("can't read {}, remove it to start over", path.display())),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(err) => Err(err).wrap_err_with(|| f!("can't read {}", path.display())),
+    }
+}
+
+/// Writes through a temp file, readers never seeing it half written
+pub fn save(path: &Path, state: &impl Serialize) -> Result<()> {
+    let tmp = path.with_extension(f!("{}.tmp", std::process::id()));
+    std::fs::write(&tmp, serde_json::to_string(state)?).wrap_err_with(|| f!("can't write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).wrap_err_with(|| f!("can't write {}", path.display()))
+}
+
+/// Loads the state at `path`, lets `change` modify it and saves it, all under a lock, so processes changing it
+/// concurrently, e.g. tunnel supervisors, don't lose each other's changes
+pub fn update<T, R>(path: &Path, change: impl FnOnce(&mut T) -> Result<R>) -> Result<R>
+    where T: Serialize + DeserializeOwned + Default
+{
+    let lock_path = path.with_extension("lock");
+    let lock = File::create(&lock_path).wrap_err_with(|| f!("can't create {}", lock_path.display()))?;
+    lock.lock_exclusive().wrap_err_with(|| f!("can't lock {}", lock_path.display()))?;
+    let mut state = load(path)?;
+    let res = change(&mut state)?;
+    save(path, &state)?;
+    // closing the file releases the lock
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn state_round_trips_under_concurrent_updates_and_corrupt_files_fail() {
+        let path = std::env::temp_dir().join(f!("tt-state-test-{}", std::process::id()));
+        assert_eq!(load::<BTreeMap<String, u32>>(&path).unwrap(), BTreeMap::new());
+        save(&path, &BTreeMap::from([("k1", 1)])).unwrap();
+        assert_eq!(load::<BTreeMap<String, u32>>(&path).unwrap()["k1"], 1);
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                         update(&path, |x: &mut BTreeMap<String, u32>| {
+                             *x.entry("k1".into()).or_default() += 1;
+                             Ok(())
+                         }).unwrap()
+                     });
+            }
+        });
+        assert_eq!(load::<BTreeMap<String, u32>>(&path).unwrap()["k1"], 9);
+        std::fs::write(&path, "{\"k1\": ").unwrap();
+        assert!(load::<BTreeMap<String, u32>>(&path).is_err());
+        std::fs::remove_file(path.with_extension("lock")).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+use crate::prelude::*;
+use crate::process;
+use crate::settings::COMMON_TSH_ARGS;
+use crate::teleport::Host;
+use itertools::Itertools;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    pub size: u64,
+    pub mtime: i64,
+    pub sha256: Option<String>,
+}
+
+/// Files by path relative to the synced directory, '/' separated
+pub type Listing = BTreeMap<String, FileInfo>;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Plan {
+    pub copy: Vec<String>,
+    pub delete: Vec<String>,
+}
+
+/// rsync-like excludes: `*` and `?` stay within a path segment, `**` crosses them,
+/// patterns without '/' are matched against every segment
+pub struct Excludes(Vec<(bool, Regex)>);
+
+impl Excludes {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let excludes = patterns.iter()
+                               .map(|x| {
+                                   let re = regex::escape(x.trim_matches('/')).replace(r"\*\*", ".*")
+                                                                              .replace(r"\*", "[^/]*")
+                                                                              .replace(r"\?", "[^/]");
+                                   Ok((x.contains('/'), Regex::new(&f!("^{re}$"))?))
+                               })
+                               .collect::<Result<_>>()?;
+        Ok(Self(excludes))
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.0.iter().any(|(full, re)| {
+                         if *full {
+                             re.is_match(path) || path.match_indices('/').any(|(i, _)| re.is_match(&path[..i]))
+                         } else {
+                             path.split('/').any(|x| re.is_match(x))
+                         }
+                     })
+    }
+}
+
+pub fn plan(src: &Listing, dst: &Listing, delete: bool, excludes: &Excludes) -> Plan {
+    let differs = |a: &FileInfo, b: &FileInfo| match (&a.sha256, &b.sha256) {
+        (Some(a), Some(b)) => a != b,
+        _ => a.size != b.size || a.mtime != b.mtime,
+    };
+    let copy = src.iter()
+                  .filter(|(path, info)| dst.get(*path).is_none_or(|x| differs(info, x)))
+                  .map(|(path, _)| path.clone())
+                  .filter(|x| !excludes.is_excluded(x))
+                  .collect();
+    let delete = if delete {
+        dst.keys().filter(|x| !src.contains_key(*x) && !excludes.is_excluded(x)).cloned().collect()
+    } else {
+        vec![]
+    };
+    Plan { copy, delete }
+}
+
+/// A missing `dir` is empty only when `missing_ok`, i.e. when it's the destination
+pub fn list_local(dir: &Path, checksum: bool, missing_ok: bool) -> Result<Listing> {
+    fn walk(root: &Path, dir: &Path, checksum: bool, listing: &mut Listing) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(root, &path, checksum, listing)?;
+                continue;
+            }
+            let meta = path.metadata()?;
+            let mtime = meta.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            let sha256 = if checksum { Some(hex(&Sha256::digest(std::fs::read(&path)?))) } else { None };
+            let rel = path.strip_prefix(root)?.components().map(|x| x.as_os_str().to_string_lossy()).join("/");
+            listing.insert(rel, FileInfo { size: meta.len(), mtime, sha256 });
+        }
+        Ok(())
+    }
+    let mut listing = Listing::new();
+    if dir.is_dir() {
+        walk(dir, dir, checksum, &mut listing)?;
+    } else {
+        ensure!(missing_ok && !dir.exists(), "{} is not a directory", dir.display());
+    }
+    Ok(listing)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| f!("{x:02x}")).collect()
+}
+
+fn tsh(host: &Host, command: &str) -> Command {
+    let mut cmd = Command::new("tsh");
+    cmd.args(COMMON_TSH_ARGS).args(["ssh", &host.login(), command]);
+    cmd
+}
+
+/// Parses `find -printf '%P\t%s\t%T@\n'` lines, then `sha256sum` lines when present
+fn parse_remote_listing(out: &str) -> Listing {
+    let mut listing = Listing::new();
+    for line in out.lines() {
+        let parts = line.split('\t').collect_vec();
+        if let [path, size, mtime] = parts[..] {
+            let (Ok(size), Ok(mtime)) = (size.parse(), mtime.split('.').next().unwrap_or_default().parse()) else {
+                continue;
+            };
+            listing.insert(path.to_owned(), FileInfo { size, mtime, sha256: None });
+        } else if let Some((hash, path)) = line.split_once("  ") {
+            if let Some(info) = listing.get_mut(path.trim_start_matches("./")) {
+                info.sha256 = Some(hash.to_owned());
+            }
+        }
+    }
+    listing
+}
+
+/// Shell command printing what `parse_remote_listing` reads, failing on a missing `dir` unless `missing_ok`
+fn listing_command(dir: &str, checksum: bool, missing_ok: bool) -> String {
+    let dir = shell_quote(dir);
+    let missing = if missing_ok { "exit 0" } else { "echo \"no such directory\" >&2; exit 2" };
+    let sums = if checksum { " && find . -type f -exec sha256sum {} +" } else { "" };
+    f!("if [ ! -e {dir} ]; then {missing}; fi; cd {dir} && find . -type f -printf '%P\\t%s\\t%T@\\n'{sums}")
+}
+
+/// Same as `list_local`, `dir` being on `host`
+pub fn list_remote(host: &Host, dir: &str, checksum: bool, missing_ok: bool) -> Result<Listing> {
+    let out = tsh(host, &listing_command(dir, checksum, missing_ok)).query()?;
+    ensure!(out.status.success(),
+            "can't list {dir}: {}",
+            String::from_utf8_lossy(&out.stderr).trim());
+    Ok(parse_remote_listing(&String::from_utf8_lossy(&out.stdout)))
+}
+
+/// Pipes a tar of `files` from `from` into a tar extracting to `to`
+fn pipe_tar(mut from: Command, mut to: Command, files: &[String], list_via_stdin: bool) -> Result<()> {
+    let mut src = from.stdin(Stdio::piped()).stdout(Stdio::piped()).run_spawn()?;
+    let mut dst = to.stdin(src.stdout.take().context("can't take tar stdout")?).run_spawn()?;
+    let mut stdin = src.stdin.take().context("can't take tar stdin")?;
+    if list_via_stdin {
+        stdin.write_all(files.join("\n").as_bytes())?;
+    }
+    drop(stdin);
+    ensure!(src.wait()?.success(), "tar failed on the source side");
+    ensure!(dst.wait()?.success(), "tar failed on the destination side");
+    Ok(())
+}
+
+pub fn upload(host: &Host, local: &Path, remote: &str, plan: &Plan) -> Result<()> {
+    let remote = shell_quote(remote);
+    if !plan.copy.is_empty() {
+        let mut tar = Command::new("tar");
+        tar.arg("-cf").arg("-").arg("-C").arg(local).arg("-T").arg("-");
+        pipe_tar(tar,
+                 tsh(host, &f!("mkdir -p {remote} && tar -xpf - -C {remote}")),
+                 &plan.copy,
+                 true)?;
+    }
+    if !plan.delete.is_empty() {
+        let files = plan.delete.iter().map(|x| shell_quote(x)).join(" ");
+        let out = tsh(host, &f!("cd {remote} && rm -f -- {files}")).run_output()?;
+        ensure!(out.status.success(),
+                "can't delete: {}",
+                String::from_utf8_lossy(&out.stderr).trim());
+    }
+    Ok(())
+}
+
+pub fn download(host: &Host, remote: &str, local: &Path, plan: &Plan) -> Result<()> {
+    // tar only prints under --dry-run, local changes must not happen either
+    let dry_run = process::is_dry_run();
+    if !plan.copy.is_empty() {
+        if !dry_run {
+            std::fs::create_dir_all(local)?;
+        }
+        let mut tar = Command::new("tar");
+        tar.arg("-xf").arg("-").arg("-C").arg(local);
+        pipe_tar(tsh(host, &f!("cd {} && tar -cf - -T -", shell_quote(remote))),
+                 tar,
+                 &plan.copy,
+                 true)?;
+    }
+    if !dry_run {
+        for file in &plan.delete {
+            std::fs::remove_file(local.join(file))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(size: u64, mtime: i64) -> FileInfo {
+        FileInfo { size, mtime, sha256: None }
+    }
+
+    #[test]
+    fn plan_copies_changed_and_deletes_extra() {
+        let src = Listing::from([("a".into(), info(1, 1)),
+                                 ("b/c".into(), info(2, 2)),
+                                 ("d.log".into(), info(3, 3))]);
+        let dst = Listing::from([("a".into(), info(1, 1)),
+                                 ("b/c".into(), info(2, 5)),
+                                 ("x".into(), info(1, 1))]);
+        let excludes = Excludes::new(&["*.log".into()]).unwrap();
+        assert_eq!(plan(&src, &dst, true, &excludes),
+                   Plan { copy: vec!["b/c".into()], delete: vec!["x".into()] });
+        assert_eq!(plan(&src, &dst, false, &excludes).delete, Vec::<String>::new());
+    }
+
+    #[test]
+    fn excludes_match_segments_and_paths() {
+        let excludes = Excludes::new(&[".git".into(), "target/**".into(), "*.tmp".into()]).unwrap();
+        assert!(excludes.is_excluded(".git/config"));
+        assert!(excludes.is_excluded("target/debug/tt"));
+        assert!(excludes.is_excluded("src/a.tmp"));
+        assert!(!excludes.is_excluded("src/target.rs"));
+    }
+
+    #[test]
+    fn parse_remote_listing_reads_find_and_sha256sum() {
+        let listing = parse_remote_listing("a b\t12\t1666000000.5\nsub/c\t3\t1666000001.0\nabc123  ./a b\n");
+        assert_eq!(listing["a b"],
+                   FileInfo { size: 12, mtime: 1666000000, sha256: Some("abc123".into()) });
+        assert_eq!(listing["sub/c"], info(3, 1666000001));
+    }
+
+    /// Download pipeline with both sides local, the remote listing run by sh
+    #[cfg(unix)]
+    #[test]
+    fn download_with_delete_mirrors_source_and_refuses_missing_one() {
+        let root = std::env::temp_dir().join(f!("tt-sync-test-{}", std::process::id()));
+        let (src, dst, missing) = (root.join("src"), root.join("dst"), root.join("missing"));
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::create_dir_all(&dst).unwrap();
+        std::fs::write(src.join("sub/a"), "a").unwrap();
+        std::fs::write(dst.join("p"), "p").unwrap();
+        let sh = |dir: &Path, missing_ok| {
+            let cmd = listing_command(&dir.to_string_lossy(), true, missing_ok);
+            Command::new("sh").arg("-c").arg(cmd).output().unwrap()
+        };
+
+        let remote = parse_remote_listing(&String::from_utf8_lossy(&sh(&src, false).stdout));
+        let plan = plan(&remote,
+                        &list_local(&dst, true, true).unwrap(),
+                        true,
+                        &Excludes::new(&[]).unwrap());
+        assert_eq!(plan, Plan { copy: vec!["sub/a".into()], delete: vec!["p".into()] });
+        let mut from = Command::new("tar");
+        from.arg("-cf").arg("-").arg("-C").arg(&src).arg("-T").arg("-");
+        let mut to = Command::new("tar");
+        to.arg("-xf").arg("-").arg("-C").arg(&dst);
+        pipe_tar(from, to, &plan.copy, true).unwrap();
+        assert_eq!(std::fs::read_to_string(dst.join("sub/a")).unwrap(), "a");
+
+        assert!(!sh(&missing, false).status.success());
+        let out = sh(&missing, true);
+        assert!(out.status.success() && out.stdout.is_empty());
+        assert!(list_local(&missing, false, false).is_err());
+        assert!(list_local(&missing, false, true).unwrap().is_empty());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
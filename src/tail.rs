@@ -0,0 +1,157 @@
+use crate::platform::Platform;
+use crate::prelude::*;
+use crate::process;
+use crate::settings::COMMON_TSH_ARGS;
+use crate::teleport::Host;
+use clap::ValueEnum;
+use dialoguer::console::{Color, Style};
+use regex::Regex;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(3);
+const COLORS: &[Color] = &[Color::Cyan,
+                           Color::Magenta,
+                           Color::Yellow,
+                           Color::Green,
+                           Color::Blue,
+                           Color::Red];
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TailKind {
+    File,
+    Unit,
+    Container,
+}
+
+impl TailKind {
+    /// Paths contain '/' or, on Windows, '\', units end with '.service', anything else is a container
+    pub fn guess(target: &str) -> Self {
+        if target.contains(['/', '\\']) {
+            TailKind::File
+        } else if target.ends_with(".service") {
+            TailKind::Unit
+        } else {
+            TailKind::Container
+        }
+    }
+
+    pub fn command(&self, target: &str, lines: u32, platform: Platform) -> Result<String> {
+        let quoted = platform.quote(target);
+        let command = match (self, platform) {
+            (TailKind::File, Platform::Lnx) => f!("tail -n {lines} -F {quoted}"),
+            (TailKind::File, Platform::Win) => f!("Get-Content -Wait -Tail {lines} -LiteralPath {quoted}"),
+            (TailKind::Unit, Platform::Lnx) => f!("journalctl -f -n {lines} -u {quoted}"),
+            (TailKind::Unit, Platform::Win) => bail!("journald units don't exist on Windows"),
+            (TailKind::Container, _) => f!("{}d logs -f --tail {lines} {quoted} 2>&1", platform.docker()),
+        };
+        Ok(platform.script(&command))
+    }
+}
+
+/// Follows `target` on every host, prefixing lines with the colored host name, until interrupted
+pub fn follow(hosts: &[Host], kind: TailKind, target: &str, lines: u32, filter: Option<&Regex>) -> Result<()> {
+    let commands = commands(hosts, kind, target, lines)?;
+    if process::is_dry_run() {
+        // a no-op would stand in for tsh, reconnecting to it would never end
+        for mut cmd in commands {
+            cmd.run()?;
+        }
+        return Ok(());
+    }
+    let width = hosts.iter().map(|h| h.name().len()).max().unwrap_or_default();
+    std::thread::scope(|scope| {
+        for (i, (host, cmd)) in hosts.iter().zip(commands).enumerate() {
+            let prefix = Style::new().fg(COLORS[i % COLORS.len()]).apply_to(f!("{:width$} |", host.name())).to_string();
+            scope.spawn(move || {
+                     let mut cmd = cmd;
+                     loop {
+                         if let Err(err) = stream(&mut cmd, &prefix, filter) {
+                             eprintln!("{prefix} {err}");
+                         }
+                         eprintln!("{prefix} disconnected, reconnecting in {}s", RECONNECT_DELAY.as_secs());
+                         std::thread::sleep(RECONNECT_DELAY);
+                         // lines before the drop were already printed
+                         match kind.command(target, 0, host.platform()) {
+                             Ok(command) => cmd = tsh(host, &command),
+                             Err(err) => eprintln!("{prefix} {err}"),
+                         }
+                     }
+                 });
+        }
+    });
+    Ok(())
+}
+
+/// `tsh` following `target` on every host, failing before any starts if one of them can't
+fn commands(hosts: &[Host], kind: TailKind, target: &str, lines: u32) -> Result<Vec<Command>> {
+    hosts.iter()
+         .map(|host| {
+             let command = kind.command(target, lines, host.platform())
+                               .wrap_err_with(|| f!("can't follow {target} on {}", host.name()))?;
+             Ok(tsh(host, &command))
+         })
+         .collect()
+}
+
+fn tsh(host: &Host, command: &str) -> Command {
+    let mut cmd = Command::new("tsh");
+    cmd.args(COMMON_TSH_ARGS).args(["ssh", &host.login(), command]);
+    cmd
+}
+
+fn stream(cmd: &mut Command, prefix: &str, filter: Option<&Regex>) -> Result<()> {
+    let mut child = cmd.stdin(Stdio::null()).stdout(Stdio::piped()).run_spawn()?;
+    let stdout = child.stdout.take().context("can't take stdout")?;
+    prefix_lines(stdout, std::io::stdout(), prefix, filter)?;
+    child.wait()?;
+    Ok(())
+}
+
+/// Copies the lines of `from` matching `filter` to `to` behind `prefix`, binary or Latin-1 ones lossily
+fn prefix_lines(from: impl Read, mut to: impl Write, prefix: &str, filter: Option<&Regex>) -> Result<()> {
+    for line in BufReader::new(from).split(b'\n') {
+        let line = line?;
+        let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(&line));
+        if filter.is_some_and(|re| !re.is_match(&line)) {
+            continue;
+        }
+        writeln!(to, "{prefix} {line}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    #[test]
+    fn guess_tells_paths_units_and_containers_apart() {
+        assert_eq!(TailKind::guess("/var/log/syslog"), TailKind::File);
+        assert_eq!(TailKind::guess("logs/app.log"), TailKind::File);
+        assert_eq!(TailKind::guess(r"C:\logs\app.log"), TailKind::File);
+        assert_eq!(TailKind::guess("nginx.service"), TailKind::Unit);
+        assert_eq!(TailKind::guess("api"), TailKind::Container);
+        assert_eq!(TailKind::guess("app.log"), TailKind::Container);
+    }
+
+    #[test]
+    fn commands_connect_once_per_host_up_front() {
+        let hosts = [Host::test("k1", "db-primary", &[]), Host::test("k2", "web-1", &[])];
+        let cmds = commands(&hosts, TailKind::File, "/var/log/syslog", 10).unwrap();
+        let args = cmds.iter().map(|x| x.get_args().skip(COMMON_TSH_ARGS.len()).collect_vec()).collect_vec();
+        assert_eq!(args,
+                   [["ssh", "ubuntu@db-primary", "tail -n 10 -F '/var/log/syslog'"],
+                    ["ssh", "ubuntu@web-1", "tail -n 10 -F '/var/log/syslog'"]]);
+    }
+
+    #[test]
+    fn prefix_lines_survives_non_utf8_and_filters() {
+        let (re, mut out) = (Regex::new("error").unwrap(), vec![]);
+        prefix_lines(&b"caf\xe9 error\r\nok\nerror 2\n"[..], &mut out, "k1 |", Some(&re)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "k1 | caf\u{fffd} error\nk1 | error 2\n");
+    }
+}
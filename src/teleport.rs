@@ -1,6 +1,11 @@
+use crate::platform::Platform;
+use crate::prelude::*;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
 
 pub type Hosts = Vec<Host>;
 
@@ -10,6 +15,8 @@ pub struct Host {
     pub version: String,
     pub metadata: Metadata,
     pub spec: Spec,
+    #[serde(skip)]
+    platform: Platform,
 }
 
 impl Host {
@@ -25,6 +32,72 @@ impl Host {
     pub fn key(&self) -> &str {
         &self.metadata.name
     }
+
+    /// True when every label in `selector` is on the host, `*` matching any value
+    pub fn matches(&self, selector: &BTreeMap<String, String>) -> bool {
+        selector.iter().all(|(k, v)| match self.metadata.labels.get(k) {
+                           Some(label) => v == "*" || label == v,
+                           None => false,
+                       })
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Sets the platform from the `label` label, or from the other labels and the hostname
+    pub fn detect_platform(&mut self, label: &str) {
+        self.platform = Platform::detect(&self.metadata.labels, label, &self.spec.hostname);
+    }
+
+    /// Default login for the platform, e.g. `ubuntu@web-1`
+    pub fn login(&self) -> String {
+        format!("{}@{}", self.platform.login(), self.spec.hostname)
+    }
+
+    /// Same as `login` with the name the generated ssh config knows, for scp and vscode
+    pub fn ssh_login(&self) -> String {
+        format!("{}@{}", self.platform.login(), self.ssh_name())
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        match name {
+            "hostname" => Some(self.spec.hostname.clone()),
+            "ssh_name" => Some(self.ssh_name()),
+            "addr" => Some(self.spec.addr.clone()),
+            "key" => Some(self.key().to_owned()),
+            _ => name.strip_prefix("label:").and_then(|l| self.metadata.labels.get(l).cloned()),
+        }
+    }
+
+    /// Replaces `{hostname}`, `{ssh_name}`, `{addr}`, `{key}` and `{label:<name>}` in `template` with their value
+    /// passed through `quote`, failing on any other lowercase name or a label the host doesn't carry. Braces around
+    /// anything else, e.g. `awk '{print $1}'`, `${HOME}` or `{{.Names}}`, are left untouched
+    pub fn render(&self, template: &str, quote: impl Fn(&str) -> String) -> Result<String> {
+        let mut res = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some((head, tail)) = rest.split_once('{') {
+            res.push_str(head);
+            let Some((name, after)) = tail.split_once('}') else {
+                res.push('{');
+                rest = tail;
+                break;
+            };
+            let variable = !head.ends_with('$')
+                           && (name.starts_with("label:")
+                               || !name.is_empty() && name.chars().all(|x| x.is_ascii_lowercase() || x == '_'));
+            match (self.var(name), name.strip_prefix("label:")) {
+                _ if !variable => res.push_str(&format!("{{{name}}}")),
+                (Some(value), _) => res.push_str(&quote(&value)),
+                (None, Some(label)) => bail!("{} has no label {label}", self.name()),
+                (None, None) => bail!("unknown variable {{{name}}}, known ones are {{hostname}}, {{ssh_name}}, \
+                                       {{addr}}, {{key}} and {{label:<name>}}"),
+            }
+            rest = after;
+        }
+        res.push_str(rest);
+        Ok(res)
+    }
 }
 
 impl Display for Host {
@@ -67,3 +140,45 @@ pub struct Spec {
     pub version: String,
     pub public_addr: Option<String>,
 }
+
+#[cfg(test)]
+impl Host {
+    /// Node with key `key` and hostname `hostname`, labeled with `labels`
+    pub fn test(key: &str, hostname: &str, labels: &[(&str, &str)]) -> Self {
+        let labels = labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Self { kind: "node".into(),
+               version: "v2".into(),
+               metadata: Metadata { name: key.into(), labels, expires: String::new(), id: 0.0 },
+               spec: Spec { addr: "10.0.0.1:3022".into(),
+                            hostname: hostname.into(),
+                            use_tunnel: None,
+                            version: "12".into(),
+                            public_addr: None },
+               platform: Platform::default() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_replaces_known_variables() {
+        const HOST: &str = r#"
+{
+    "kind": "node", "version": "v2",
+    "metadata": { "name": "k1", "labels": { "cluster": "aws", "service": "api", "motd": "hi; rm -rf /" }, "expires": "", "id": 1 },
+    "spec": { "addr": "10.0.0.1:3022", "hostname": "web-1", "version": "12" }
+}
+"#;
+
+        let host: Host = serde_json::from_str(HOST).unwrap();
+        let res = host.render("{hostname} {ssh_name} {addr} {key} {label:service} awk '{print $1}' {",
+                              str::to_owned);
+        assert_eq!(res.unwrap(), "web-1 web-1.aws 10.0.0.1:3022 k1 api awk '{print $1}' {");
+        assert!(host.render("{label:none}", str::to_owned).is_err());
+        assert!(host.render("{hostnmae}", str::to_owned).is_err());
+        let res = host.render("echo {label:motd} ${HOME} {{.Names}}", shell_quote);
+        assert_eq!(res.unwrap(), "echo 'hi; rm -rf /' ${HOME} {{.Names}}");
+    }
+}
@@ -0,0 +1,317 @@
+use crate::exit;
+use crate::platform::Platform;
+use crate::prelude::*;
+use crate::process;
+use crate::settings::COMMON_TSH_ARGS;
+use crate::state;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Login of tunnels saved before hosts had a platform
+fn default_user() -> String {
+    Platform::Lnx.login().to_owned()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Forward {
+    /// `-L`, local port to a port on the node
+    Local,
+    /// `-D`, SOCKS proxy on the local port
+    Dynamic,
+    /// `-R`, port on the node to a local port
+    Remote,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Tunnel {
+    pub id: u32,
+    /// Pid of the `tt tunnels supervise` process keeping the tunnel up
+    pub pid: u32,
+    pub host: String,
+    /// Ssh login on the host, depends on its platform
+    #[serde(default = "default_user")]
+    pub user: String,
+    pub forward: Forward,
+    pub local: u16,
+    pub remote: u16,
+    pub started: String,
+    /// Times the supervisor restarted tsh
+    #[serde(default)]
+    pub restarts: u32,
+    /// How tsh last exited, with its last error line, e.g. a port already in use
+    #[serde(default)]
+    pub last_exit: Option<String>,
+}
+
+impl Tunnel {
+    pub fn new(host: &str, user: &str, forward: Forward, local: u16, remote: u16) -> Self {
+        Self { id: 0,
+               pid: 0,
+               host: host.to_owned(),
+               user: user.to_owned(),
+               forward,
+               local,
+               remote,
+               started: now(),
+               restarts: 0,
+               last_exit: None }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.forward {
+            Forward::Local => f!("localhost:{} -> {}:{}", self.local, self.host, self.remote),
+            Forward::Dynamic => f!("{} via {}", self.proxy_url(), self.host),
+            Forward::Remote => f!("{}:{} -> localhost:{}", self.host, self.remote, self.local),
+        }
+    }
+
+    pub fn proxy_url(&self) -> String {
+        f!("socks5h://127.0.0.1:{}", self.local)
+    }
+
+    /// Proxy auto-config sending only `domains` (and their subdomains) through the SOCKS proxy
+    pub fn pac(&self, domains: &[String]) -> String {
+        let proxy = f!("SOCKS5 127.0.0.1:{0}; SOCKS 127.0.0.1:{0}", self.local);
+        let conditions = domains.iter()
+                                .map(|d| d.trim_start_matches('.'))
+                                .map(|d| f!("host == \"{d}\" || dnsDomainIs(host, \".{d}\")"))
+                                .join("\n        || ");
+        f!(r#"function FindProxyForURL(url, host) {{
+    if ({conditions}) {{
+        return "{proxy}";
+    }}
+    return "DIRECT";
+}}
+"#)
+    }
+
+    pub fn command(&self) -> Command {
+        let spec = match self.forward {
+            Forward::Local => ["-L".to_owned(), f!("{}:localhost:{}", self.local, self.remote)],
+            Forward::Dynamic => ["-D".to_owned(), self.local.to_string()],
+            Forward::Remote => ["-R".to_owned(), f!("{}:localhost:{}", self.remote, self.local)],
+        };
+        let mut cmd = Command::new("tsh");
+        cmd.args(COMMON_TSH_ARGS).args(["ssh", "-N"]).args(spec).arg(f!("{}@{}", self.user, self.host));
+        cmd
+    }
+
+    /// `alive`, `dead`, or how tsh failed when the supervisor had to restart it
+    pub fn status(&self) -> String {
+        match &self.last_exit {
+            _ if !self.is_alive() => "dead".into(),
+            None => "alive".into(),
+            Some(exit) => f!("alive, tsh restarted {} times, last {exit}", self.restarts),
+        }
+    }
+
+    /// Whether `pid` is still this tunnel's supervisor, not a process that got the pid once it ended
+    fn is_alive(&self) -> bool {
+        if self.pid == 0 {
+            return false;
+        }
+        let pid = self.pid.to_string();
+        let out = if cfg!(windows) {
+            let filter = f!("(Get-CimInstance Win32_Process -Filter 'ProcessId={pid}').CommandLine");
+            Command::new("powershell").args(["-NoProfile", "-Command", &filter]).query()
+        } else {
+            Command::new("ps").args(["-o", "args=", "-p", &pid]).query()
+        };
+        out.is_ok_and(|x| runs_supervisor(&String::from_utf8_lossy(&x.stdout), self.id))
+    }
+
+    /// Kills the supervisor together with the tsh process it spawned
+    fn kill(&self) -> Result<()> {
+        let pid = self.pid.to_string();
+        if cfg!(windows) {
+            Command::new("taskkill").args(["/T", "/F", "/PID", &pid]).stdout(Stdio::null()).run()?;
+        } else {
+            Command::new("kill").args(["-TERM", "--", &f!("-{pid}")]).stderr(Stdio::null()).run()?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `command_line` is `tt tunnels supervise <id>`
+fn runs_supervisor(command_line: &str, id: u32) -> bool {
+    command_line.split_whitespace().collect_vec().ends_with(&["tunnels", "supervise", &id.to_string()])
+}
+
+/// Lowercased sshd options from `sshd -T` or `sshd_config` output, first occurrence wins like in sshd
+pub fn parse_sshd_options(text: &str) -> HashMap<String, String> {
+    let mut options = HashMap::new();
+    for line in text.lines().map(str::trim).filter(|x| !x.is_empty() && !x.starts_with('#')) {
+        if let Some((key, value)) = line.split_once(char::is_whitespace) {
+            options.entry(key.to_lowercase()).or_insert_with(|| value.trim().to_lowercase());
+        }
+    }
+    options
+}
+
+/// Detached tunnels, persisted so that `tt tunnels` can find them from any terminal
+#[derive(Serialize, Deserialize, Default)]
+pub struct Tunnels {
+    pub(crate) entries: Vec<Tunnel>,
+}
+
+impl Tunnels {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        state::load(path.as_ref())
+    }
+
+    /// Changes the tunnels under a lock, supervisors and `tt tunnels` writing them concurrently
+    fn update<R>(path: impl AsRef<Path>, change: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        state::update(path.as_ref(), change)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Tunnel> {
+        self.entries.iter().find(|x| x.id == id)
+    }
+
+    fn get_mut(&mut self, id: u32) -> Option<&mut Tunnel> {
+        self.entries.iter_mut().find(|x| x.id == id)
+    }
+
+    /// Starts a supervisor in the background for `tunnel`, restarting tsh whenever it exits
+    pub fn spawn(path: impl AsRef<Path>, mut tunnel: Tunnel) -> Result<Tunnel> {
+        if process::is_dry_run() {
+            // nothing to supervise, so no entry either
+            p!("{}", process::display(&tunnel.command()));
+            return Ok(tunnel);
+        }
+        tunnel.id = Tunnels::update(&path, |tunnels| {
+            let id = tunnels.entries.iter().map(|x| x.id).max().unwrap_or_default() + 1;
+            tunnels.entries.push(Tunnel { id, ..tunnel.clone() });
+            Ok(id)
+        })?;
+        let mut cmd = Command::new(std::env::current_exe()?);
+        cmd.args(["tunnels", "supervise", &tunnel.id.to_string()])
+           .stdin(Stdio::null())
+           .stdout(Stdio::null())
+           .stderr(Stdio::null());
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            cmd.process_group(0);
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const DETACHED_PROCESS: u32 = 0x00000008;
+            const CREATE_NEW_PROCESS_GROUP: u32 = 0x00000200;
+            cmd.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+        }
+        tunnel.pid = cmd.run_spawn()?.id();
+        Tunnels::update(&path, |tunnels| {
+            if let Some(x) = tunnels.get_mut(tunnel.id) {
+                x.pid = tunnel.pid;
+            }
+            Ok(())
+        })?;
+        Ok(tunnel)
+    }
+
+    /// Keeps tunnel `id` up until its entry is removed by `stop`
+    pub fn supervise(path: impl AsRef<Path>, id: u32) -> Result<()> {
+        // `spawn` may not have recorded the pid yet, nor will `stop` find the supervisor once the entry is gone
+        let pid = std::process::id();
+        let mut current = Tunnels::update(&path, |tunnels| {
+            let Some(tunnel) = tunnels.get_mut(id) else {
+                return Ok(None);
+            };
+            tunnel.pid = pid;
+            Ok(Some(tunnel.clone()))
+        })?;
+        while let Some(tunnel) = current {
+            let exit = match exit::status(tunnel.command().stdin(Stdio::null())) {
+                Ok((status, Some(error))) => f!("{status} at {}: {error}", now()),
+                Ok((status, None)) => f!("{status} at {}", now()),
+                Err(err) => f!("failing to start at {}: {err}", now()),
+            };
+            Tunnels::update(&path, |tunnels| {
+                if let Some(x) = tunnels.get_mut(id) {
+                    x.restarts += 1;
+                    x.last_exit = Some(exit);
+                }
+                Ok(())
+            })?;
+            std::thread::sleep(RESTART_DELAY);
+            current = Tunnels::load(&path)?.get(id).cloned();
+        }
+        Ok(())
+    }
+
+    /// Removes tunnel `id`, or every one, then kills their supervisors
+    pub fn stop(path: impl AsRef<Path>, id: Option<u32>) -> Result<()> {
+        let stopped = Tunnels::update(path, |tunnels| {
+            let (stopped, kept) =
+                std::mem::take(&mut tunnels.entries).into_iter().partition(|x| id.is_none() || id == Some(x.id));
+            tunnels.entries = kept;
+            Ok(stopped)
+        })?;
+        for tunnel in stopped.iter().filter(|x| x.is_alive()) {
+            tunnel.kill()?;
+        }
+        if let Some(id) = id {
+            ensure!(!stopped.is_empty(), "No tunnel with id {id}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sshd_options_keeps_first_occurrence() {
+        const SSHD: &str = r#"
+# comment
+AllowTcpForwarding local
+
+GatewayPorts  yes
+allowtcpforwarding yes
+"#;
+
+        let options = parse_sshd_options(SSHD);
+        assert_eq!(options["allowtcpforwarding"], "local");
+        assert_eq!(options["gatewayports"], "yes");
+    }
+
+    #[test]
+    fn runs_supervisor_matches_only_its_own_tunnel() {
+        assert!(runs_supervisor("/usr/local/bin/tt tunnels supervise 3\n", 3));
+        assert!(runs_supervisor(r#""C:\Users\me\scoop\shims\tt.exe" tunnels supervise 3"#, 3));
+        assert!(!runs_supervisor("/usr/local/bin/tt tunnels supervise 13", 3));
+        assert!(!runs_supervisor("vim notes.txt", 3));
+        assert!(!runs_supervisor("", 3));
+    }
+
+    #[test]
+    fn pac_routes_only_configured_domains() {
+        let tunnel = Tunnel::new("web-1", "ubuntu", Forward::Dynamic, 1080, 0);
+        let pac = tunnel.pac(&["corp.internal".into(), ".svc.local".into()]);
+        assert_eq!(
+                   pac,
+                   r#"function FindProxyForURL(url, host) {
+    if (host == "corp.internal" || dnsDomainIs(host, ".corp.internal")
+        || host == "svc.local" || dnsDomainIs(host, ".svc.local")) {
+        return "SOCKS5 127.0.0.1:1080; SOCKS 127.0.0.1:1080";
+    }
+    return "DIRECT";
+}
+"#
+        );
+    }
+}
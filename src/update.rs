@@ -0,0 +1,161 @@
+use crate::config::Config;
+use crate::prelude::*;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+const NAME: &str = env!("CARGO_PKG_NAME");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const LEGACY_NAME: &str = "ash";
+
+/// Release manifest, e.g.
+/// `{ "version": "0.4.0", "artifacts": { "win-x64": { "url": "https://.../tt-0.4.0-win-x64.zip" } } }`
+#[derive(Deserialize)]
+struct Manifest {
+    version: String,
+    artifacts: HashMap<String, Artifact>,
+}
+
+#[derive(Deserialize)]
+struct Artifact {
+    /// Zip containing the binary, or the bare binary
+    url: String,
+    /// Published hash file, `<url>.sha256` if omitted
+    sha256: Option<String>,
+}
+
+fn platform() -> String {
+    let os = match std::env::consts::OS {
+        "windows" => "win",
+        os => os,
+    };
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    f!("{os}-{arch}")
+}
+
+/// Reads an http(s) url or a local file
+fn fetch(location: &str) -> Result<Vec<u8>> {
+    if !location.starts_with("http://") && !location.starts_with("https://") {
+        return std::fs::read(location).wrap_err_with(|| f!("can't read {location}"));
+    }
+    let mut bytes = vec![];
+    let response = ureq::get(location).call().wrap_err_with(|| f!("can't download {location}"))?;
+    response.into_reader().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Hash from a `.sha256` file, either plain text or the UTF-16 written by `Get-FileHash > file` in release-win.ps1
+fn parse_sha256(file: &[u8]) -> Result<String> {
+    let text = match file {
+        [0xFF, 0xFE, rest @ ..] =>
+            String::from_utf16(&rest.chunks_exact(2).map(|x| u16::from_le_bytes([x[0], x[1]])).collect::<Vec<_>>())?,
+        _ => String::from_utf8_lossy(file).into_owned(),
+    };
+    let hash = text.split_whitespace().next().unwrap_or_default().to_lowercase();
+    ensure!(hash.len() == 64 && hash.chars().all(|x| x.is_ascii_hexdigit()),
+            "invalid sha256 file");
+    Ok(hash)
+}
+
+fn extract_binary(artifact: Vec<u8>, url: &str) -> Result<Vec<u8>> {
+    if !url.ends_with(".zip") {
+        return Ok(artifact);
+    }
+    let mut zip = zip::ZipArchive::new(Cursor::new(artifact))?;
+    let exe = f!("{NAME}{}", std::env::consts::EXE_SUFFIX);
+    // releases up to 0.3.x shipped the binary under its former name
+    let legacy = f!("{LEGACY_NAME}{}", std::env::consts::EXE_SUFFIX);
+    let find =
+        |exe: &str| zip.file_names().find(|x| Path::new(x).file_name() == Some(OsStr::new(exe))).map(String::from);
+    let name = find(&exe).or_else(|| find(&legacy)).ok_or_else(|| eyre!("{exe} not found in {url}"))?;
+    let mut binary = vec![];
+    zip.by_name(&name)?.read_to_end(&mut binary)?;
+    Ok(binary)
+}
+
+/// Replaces the running executable, renaming it away first since Windows can't overwrite it
+fn swap_binary(binary: &[u8]) -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let new = exe.with_extension("new");
+    std::fs::write(&new, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&new, std::fs::Permissions::from_mode(0o755))?;
+    }
+    if cfg!(windows) {
+        let old = exe.with_extension("old");
+        _ = std::fs::remove_file(&old);
+        std::fs::rename(&exe, &old)?;
+    }
+    std::fs::rename(&new, &exe)?;
+    Ok(())
+}
+
+pub fn check_update(config: &Config) -> Result<()> {
+    let Some(location) = &config.release_manifest else {
+        if !cfg!(windows) {
+            bail!("No release manifest configured, set release_manifest in {NAME} config");
+        }
+        std::process::Command::new("scoop.cmd").args(["update", "-k", NAME]).run_spawn()?;
+        return Ok(());
+    };
+    let manifest: Manifest = serde_json::from_slice(&fetch(location)?).wrap_err("Error deserializing manifest")?;
+    let latest = Version::parse(&manifest.version)?;
+    if latest <= Version::parse(VERSION)? {
+        p!("{NAME} {VERSION} is up to date");
+        return Ok(());
+    }
+    let platform = platform();
+    let artifact = manifest.artifacts.get(&platform).ok_or_else(|| eyre!("No {latest} release for {platform}"))?;
+    p!("Downloading {NAME} {latest} from {}...", artifact.url);
+    let bytes = fetch(&artifact.url)?;
+    let sha256 = artifact.sha256.clone().unwrap_or_else(|| f!("{}.sha256", artifact.url));
+    let expected = parse_sha256(&fetch(&sha256)?)?;
+    let actual = Sha256::digest(&bytes).iter().map(|x| f!("{x:02x}")).collect::<String>();
+    ensure!(actual == expected,
+            "sha256 mismatch for {}: expected {expected}, got {actual}",
+            artifact.url);
+    swap_binary(&extract_binary(bytes, &artifact.url)?)?;
+    p!("Updated {NAME} {VERSION} -> {latest}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sha256_reads_powershell_output() {
+        const HASH: &str = "9E9FB14C0EE5062E97B47FF17C20DB7D97EDD739B090B218AE4D1AFF57DE36F9";
+        let utf16 = [0xFF, 0xFE].into_iter()
+                                .chain(f!("{HASH}\r\n").encode_utf16().flat_map(|x| x.to_le_bytes()))
+                                .collect::<Vec<u8>>();
+        assert_eq!(parse_sha256(&utf16).unwrap(), HASH.to_lowercase());
+        assert_eq!(parse_sha256(f!("{HASH}  tt.zip\n").as_bytes()).unwrap(),
+                   HASH.to_lowercase());
+        assert!(parse_sha256(b"not a hash").is_err());
+    }
+
+    #[test]
+    fn extract_binary_accepts_the_legacy_name() {
+        let zip = |name: &str| {
+            let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+            zip.start_file(name, Default::default()).unwrap();
+            std::io::Write::write_all(&mut zip, b"binary").unwrap();
+            zip.finish().unwrap().into_inner()
+        };
+        let exe = std::env::consts::EXE_SUFFIX;
+        assert_eq!(extract_binary(zip(&f!("tt{exe}")), "tt.zip").unwrap(), b"binary");
+        assert_eq!(extract_binary(zip(&f!("ash{exe}")), "ash.zip").unwrap(), b"binary");
+        assert!(extract_binary(zip("readme.txt"), "x.zip").is_err());
+    }
+}